@@ -0,0 +1,125 @@
+//! Error types
+//!
+//! This module defines the error type shared by sessions, storage backends and the
+//! framework integrations, so that callers can tell a missing value, undecodable
+//! data and an unreachable backend apart.
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+
+/// Boxed error reported by a storage backend or its connection pool
+pub type BoxError = Box<dyn StdError + Send + Sync>;
+
+/// Errors reported by rsession
+///
+/// Backend and pool failures keep the original error as their source, so the full
+/// chain is available to loggers walking `Error::source`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The requested session or session value does not exist
+    NotFound,
+    /// Session data could not be serialized or deserialized
+    Serialization(serde_json::Error),
+    /// The storage backend failed or could not be reached
+    Backend(BoxError),
+    /// No connection to the storage backend could be obtained from the pool
+    Pool(BoxError),
+    /// The session ID cannot be used with the storage backend
+    InvalidId(String),
+    /// The session cookie failed signature verification or decryption
+    Tampered,
+    /// A pagination cursor was not issued by the storage backend
+    InvalidCursor(String),
+    /// The storage backend does not support the named operation
+    Unsupported(&'static str),
+}
+
+impl Error {
+    /// Wraps an error reported by a storage backend
+    pub fn backend(err: impl Into<BoxError>) -> Self {
+        Error::Backend(err.into())
+    }
+    /// Wraps an error reported by a connection pool
+    pub fn pool(err: impl Into<BoxError>) -> Self {
+        Error::Pool(err.into())
+    }
+    /// Returns true if the error reports a failing backend rather than a regular outcome
+    ///
+    /// Missing values, rejected IDs and undecodable data are answers from a working
+    /// backend; only backend and pool errors are worth retrying or failing over.
+    pub fn is_backend_failure(&self) -> bool {
+        matches!(self, Error::Backend(_) | Error::Pool(_))
+    }
+    /// HTTP status code and client-safe message used by the framework integrations
+    ///
+    /// The message never includes the underlying error, which may leak internals.
+    #[cfg(any(feature = "actix-web", feature = "tower", feature = "salvo"))]
+    pub(crate) fn response_parts(&self) -> (u16, &'static str) {
+        match self {
+            Error::NotFound => (404, "not found"),
+            Error::Serialization(_) => (500, "invalid session data"),
+            Error::Backend(_) | Error::Pool(_) => (503, "session store unavailable"),
+            Error::InvalidId(_) | Error::Tampered => (400, "invalid session cookie"),
+            Error::InvalidCursor(_) => (400, "invalid cursor"),
+            Error::Unsupported(_) => (501, "operation not supported"),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFound => f.write_str("session or session value not found"),
+            Error::Serialization(err) => write!(f, "invalid session data: {err}"),
+            Error::Backend(err) => write!(f, "session store error: {err}"),
+            Error::Pool(err) => write!(f, "session store connection unavailable: {err}"),
+            Error::InvalidId(id) => write!(f, "invalid session id: {id:?}"),
+            Error::Tampered => f.write_str("session cookie failed verification"),
+            Error::InvalidCursor(cursor) => write!(f, "invalid cursor: {cursor:?}"),
+            Error::Unsupported(operation) => {
+                write!(
+                    f,
+                    "operation not supported by the session store: {operation}"
+                )
+            }
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Serialization(err) => Some(err),
+            Error::Backend(err) | Error::Pool(err) => Some(err.as_ref()),
+            Error::NotFound
+            | Error::InvalidId(_)
+            | Error::Tampered
+            | Error::InvalidCursor(_)
+            | Error::Unsupported(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Backend(Box::new(err))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Serialization(err)
+    }
+}
+
+#[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
+impl From<sqlx::Error> for Error {
+    fn from(err: sqlx::Error) -> Self {
+        match err {
+            sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed => Error::pool(err),
+            err => Error::backend(err),
+        }
+    }
+}
@@ -1,9 +1,9 @@
-use crate::{Session, SessionBuilder, SessionInner, SessionStatus, SessionStore};
+use crate::{Error, Session, SessionBuilder, SessionInner, SessionManager, SessionStore};
 use actix_web::body::MessageBody;
 use actix_web::dev::{Payload, Service, ServiceRequest, ServiceResponse, Transform, forward_ready};
+use actix_web::http::StatusCode;
 use actix_web::http::header::SET_COOKIE;
-use actix_web::{FromRequest, HttpMessage, HttpRequest};
-use std::cell::RefCell;
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError};
 use std::future::{Ready, ready};
 use std::pin::Pin;
 use std::rc::Rc;
@@ -20,8 +20,7 @@ pub struct ActixSessionMiddleware<T>
 where
     T: SessionStore,
 {
-    builder: Rc<SessionBuilder>,
-    store: Rc<Box<T>>,
+    manager: SessionManager<T>,
 }
 
 impl<T> ActixSessionMiddleware<T>
@@ -35,8 +34,7 @@ where
     /// * `store` - Session storage backend implementation
     pub fn new(builder: SessionBuilder, store: T) -> Self {
         Self {
-            builder: Rc::new(builder),
-            store: Rc::new(Box::new(store)),
+            manager: SessionManager::new(builder, store),
         }
     }
 }
@@ -61,8 +59,7 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(ActixInnerSessionMiddleware {
             service: Rc::new(service),
-            builder: Rc::clone(&self.builder),
-            store: Rc::clone(&self.store),
+            manager: self.manager.clone(),
         }))
     }
 }
@@ -76,8 +73,7 @@ pub struct ActixInnerSessionMiddleware<S, Store>
 where
     Store: SessionStore + 'static,
 {
-    builder: Rc<SessionBuilder>,
-    store: Rc<Box<Store>>,
+    manager: SessionManager<Store>,
     service: Rc<S>,
 }
 /// Service implementation for Actix session middleware
@@ -100,67 +96,21 @@ where
     forward_ready!(service);
     fn call(&self, req: ServiceRequest) -> Self::Future {
         let service = Rc::clone(&self.service);
-        let store = Rc::clone(&self.store);
-        let builder = Rc::clone(&self.builder);
-        let _ = req.app_data().insert(&builder.clone());
+        let manager = self.manager.clone();
         Box::pin(async move {
-            let session_key = req.cookie(&builder.key).map(|x| x.value().to_string());
-            if let Some(session_key) = session_key {
-                if let Ok(inner) = store.get(&session_key).await {
-                    store.remove(&session_key).await.ok();
-                    req.extensions_mut().insert(Rc::new(RefCell::new(inner)));
-                } else {
-                    req.extensions_mut()
-                        .insert(Rc::new(RefCell::new(SessionInner::new(session_key))));
-                }
-            } else {
-                req.extensions_mut()
-                    .insert(Rc::new(RefCell::new(SessionInner::new(
-                        builder.rand_key.generate(),
-                    ))));
-            }
+            let cookie_value = req
+                .cookie(manager.cookie_name())
+                .map(|x| x.value().to_string());
+            let session = match manager.load(cookie_value.as_deref()).await {
+                Ok(session) => session,
+                Err(err) => return Err(err.into()),
+            };
+            req.extensions_mut().insert(session.clone());
             let mut res = service.call(req).await?;
-            let inner = res
-                .request()
-                .extensions()
-                .get::<Rc<RefCell<SessionInner>>>()
-                .map(|x| x.clone());
-            if let Some(status) = inner {
-                let inner = status.borrow();
-                match inner.status {
-                    SessionStatus::UnChange => {
-                        store.set(&inner.id.to_string(), inner.clone()).await.ok();
-                        if builder.auto_expire {
-                            store
-                                .expire(&inner.id.to_string(), builder.expire_time)
-                                .await
-                                .ok();
-                        }}
-                    SessionStatus::Change => {
-                        store.remove(&inner.id.to_string()).await.ok();
-                        store.set(&inner.id.to_string(), inner.clone()).await.ok();
-                        store
-                            .expire(&inner.id.to_string(), builder.expire_time)
-                            .await
-                            .ok();
-                    }
-                    SessionStatus::Clear => {
-                        store.remove(&inner.id.to_string()).await.ok();
-                    }
-                    SessionStatus::Destroy => {
-                        store.remove(&inner.id.to_string()).await.ok();
-                    }
-                    SessionStatus::Expire => {
-                        store
-                            .expire(&inner.id.to_string(), builder.expire_time)
-                            .await
-                            .ok();
-                    }
-                }
-                let cookie = builder.build(inner.id.clone());
-                if let Ok(cookie) = cookie.to_string().parse() {
-                    res.headers_mut().insert(SET_COOKIE, cookie);
-                }
+            if let Some(cookie) = manager.commit(&session).await
+                && let Ok(cookie) = cookie.to_string().parse()
+            {
+                res.headers_mut().append(SET_COOKIE, cookie);
             }
             Ok(res)
         })
@@ -187,7 +137,7 @@ impl FromRequest for Session {
     type Future = Ready<Result<Session, actix_web::Error>>;
 
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
-        let inner = match req.extensions().get::<Rc<RefCell<SessionInner>>>() {
+        let session = match req.extensions().get::<Session>() {
             Some(x) => x.clone(),
             None => {
                 let builder = match req.app_data::<Rc<SessionBuilder>>() {
@@ -198,9 +148,22 @@ impl FromRequest for Session {
                         )));
                     }
                 };
-                Rc::new(RefCell::new(SessionInner::new(builder.rand_key.generate())))
+                Session::new(SessionInner::new(builder.rand_key.generate()))
             }
         };
-        ready(Ok(Session::new(inner)))
+        ready(Ok(session))
+    }
+}
+
+/// Actix-web ResponseError implementation for rsession errors
+///
+/// Lets handlers return `rsession::Error` directly. The response carries a generic
+/// message for the status; the underlying error is never sent to the client.
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.response_parts().0).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).body(self.response_parts().1)
     }
 }
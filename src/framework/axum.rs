@@ -2,18 +2,16 @@
 //!
 //! This module provides Axum integration for session management using Tower middleware,
 //! handling session creation, storage operations, and cookie management.
-use crate::{Session, SessionBuilder, SessionInner, SessionStatus, SessionStore};
+use crate::{Error, Session, SessionBuilder, SessionManager, SessionStore};
 use axum::body::Body;
-use axum::http::HeaderMap;
 use axum::http::header::COOKIE;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
 use axum::{extract::Request, response::Response};
 use cookie::{Cookie, CookieJar};
 use futures::future::BoxFuture;
 use http::header::SET_COOKIE;
-use std::cell::RefCell;
 use std::convert::Infallible;
-use std::rc::Rc;
-use std::sync::Arc;
 use std::task::{Context, Poll};
 use tower::{Layer, Service};
 
@@ -33,8 +31,7 @@ where
     Storage: SessionStore + 'static + Send + Sync + Clone,
 {
     inner: S,
-    builder: Arc<SessionBuilder>,
-    store: Arc<Storage>,
+    manager: SessionManager<Storage>,
 }
 
 /// Tower Service implementation for Axum session middleware
@@ -56,69 +53,24 @@ where
     }
 
     fn call(&mut self, mut req: Request<Body>) -> Self::Future {
-        let store = self.store.clone();
+        let manager = self.manager.clone();
         let not_ready_inner = self.inner.clone();
         let mut ready_inner = std::mem::replace(&mut self.inner, not_ready_inner);
-        let builder = self.builder.clone();
         Box::pin(async move {
             let cookies = get_cookies(req.headers());
-            let session_key = cookies.get(&builder.key);
-            let session_inner = if let Some(session_key) = session_key {
-                let session_key = session_key.value().to_string();
-                if let Ok(inner) = store.get(&session_key).await {
-                    inner
-                } else {
-                    SessionInner::new(builder.rand_key.generate())
-                }
-            } else {
-                SessionInner::new(builder.rand_key.generate())
+            let cookie_value = cookies.get(manager.cookie_name()).map(|x| x.value());
+            let session = match manager.load(cookie_value).await {
+                Ok(session) => session,
+                Err(err) => return Ok(err.into_response()),
             };
-            let session = Session::new(Rc::new(RefCell::new(session_inner)));
             req.extensions_mut().insert(session.clone());
-            let future = ready_inner.call(req);
-            let res = future.await;
-            match res {
-                Ok(mut res) => {
-                    let inner = session.inner();
-                    let cookie = builder.build(inner.id.clone());
-                    if let Ok(cookie) = cookie.to_string().parse() {
-                        res.headers_mut().insert(SET_COOKIE, cookie);
-                    }
-                    match inner.status {
-                        SessionStatus::UnChange => {
-                            if builder.auto_expire {
-                                store
-                                    .expire(&inner.id.to_string(), builder.expire_time)
-                                    .await
-                                    .ok();
-                            }
-                            store.set(&inner.id.to_string(), inner.clone()).await.ok();
-                        }
-                        SessionStatus::Change => {
-                            store.remove(&inner.id.to_string()).await.ok();
-                            store.set(&inner.id.to_string(), inner.clone()).await.ok();
-                            store
-                                .expire(&inner.id.to_string(), builder.expire_time)
-                                .await
-                                .ok();
-                        }
-                        SessionStatus::Clear => {
-                            store.remove(&inner.id.to_string()).await.ok();
-                        }
-                        SessionStatus::Destroy => {
-                            store.remove(&inner.id.to_string()).await.ok();
-                        }
-                        SessionStatus::Expire => {
-                            store
-                                .expire(&inner.id.to_string(), builder.expire_time)
-                                .await
-                                .ok();
-                        }
-                    }
-                    Ok(res)
-                }
-                Err(err) => Err(err),
+            let mut res = ready_inner.call(req).await?;
+            if let Some(cookie) = manager.commit(&session).await
+                && let Ok(cookie) = cookie.to_string().parse()
+            {
+                res.headers_mut().append(SET_COOKIE, cookie);
             }
+            Ok(res)
         })
     }
 }
@@ -135,8 +87,7 @@ pub struct AxumSessionMiddlewareLayer<Storage>
 where
     Storage: SessionStore + 'static,
 {
-    builder: Arc<SessionBuilder>,
-    store: Arc<Storage>,
+    manager: SessionManager<Storage>,
 }
 
 impl<Storage> AxumSessionMiddlewareLayer<Storage>
@@ -150,8 +101,7 @@ where
     /// * `store` - Session storage backend implementation
     pub fn new(builder: SessionBuilder, store: Storage) -> Self {
         Self {
-            builder: Arc::new(builder),
-            store: Arc::new(store),
+            manager: SessionManager::new(builder, store),
         }
     }
 }
@@ -174,8 +124,7 @@ where
     fn layer(&self, inner: S) -> Self::Service {
         AxumSessionMiddleware {
             inner,
-            builder: self.builder.clone(),
-            store: self.store.clone(),
+            manager: self.manager.clone(),
         }
     }
 }
@@ -208,17 +157,10 @@ impl<S> axum::extract::FromRequest<S> for Session {
         req: Request,
         _: &S,
     ) -> impl Future<Output = Result<Self, Self::Rejection>> + Send {
-        async move {
-            let inner = req.extensions().get::<Session>();
-            if let Some(inner) = inner {
-                return Ok(inner.clone());
-            } else {
-                Err((
-                    axum::http::status::StatusCode::INTERNAL_SERVER_ERROR,
-                    "session not found",
-                ))
-            }
-        }
+        std::future::ready(req.extensions().get::<Session>().cloned().ok_or((
+            axum::http::status::StatusCode::INTERNAL_SERVER_ERROR,
+            "session not found",
+        )))
     }
 }
 
@@ -228,7 +170,7 @@ impl<S> axum::extract::FromRequest<S> for Session {
 //         async move {
 //             let inner = parts.extensions.get::<SessionInner>();
 //             if let Some(inner) = inner {
-//                 Ok(Session::new(Rc::new(RefCell::new(inner.clone()))))
+//                 Ok(Session::new(inner.clone()))
 //             } else {
 //                 Err((
 //                     axum::http::status::StatusCode::INTERNAL_SERVER_ERROR,
@@ -238,3 +180,18 @@ impl<S> axum::extract::FromRequest<S> for Session {
 //         }
 //     }
 // }
+
+/// Axum IntoResponse implementation for rsession errors
+///
+/// Lets handlers return `rsession::Error` directly. The response carries a generic
+/// message for the status; the underlying error is never sent to the client.
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let (status, message) = self.response_parts();
+        (
+            StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            message,
+        )
+            .into_response()
+    }
+}
@@ -2,13 +2,11 @@
 //!
 //! This module provides Salvo framework integration for session management,
 //! including middleware and extension traits for easy session access.
-use crate::{Session, SessionBuilder, SessionInner, SessionStatus, SessionStore};
+use crate::{Error, Session, SessionBuilder, SessionManager, SessionStore};
 use async_trait::async_trait;
 use http::header::SET_COOKIE;
-use salvo::{Depot, FlowCtrl, Handler, Request, Response};
-use std::cell::RefCell;
-use std::rc::Rc;
-use std::sync::Arc;
+use salvo::http::StatusCode;
+use salvo::{Depot, FlowCtrl, Handler, Request, Response, Writer};
 
 /// Salvo middleware for session management
 ///
@@ -21,8 +19,7 @@ pub struct SalvoSessionMiddleware<Storage>
 where
     Storage: SessionStore + 'static + Send + Sync + Clone,
 {
-    builder: Arc<SessionBuilder>,
-    store: Arc<Storage>,
+    manager: SessionManager<Storage>,
 }
 impl<Storage> SalvoSessionMiddleware<Storage>
 where
@@ -35,8 +32,7 @@ where
     /// * `store` - Session storage backend implementation
     pub fn new(builder: SessionBuilder, store: Storage) -> Self {
         Self {
-            builder: Arc::new(builder),
-            store: Arc::new(store),
+            manager: SessionManager::new(builder, store),
         }
     }
 }
@@ -59,57 +55,24 @@ where
         res: &mut Response,
         ctrl: &mut FlowCtrl,
     ) {
-        let builder = self.builder.clone();
-        let store = self.store.clone();
-        let cookies = req.cookies();
-        let inner = match cookies.get(&self.builder.key) {
-            Some(cookie) => {
-                let session_id = cookie.value();
-                store
-                    .get(session_id)
-                    .await
-                    .unwrap_or(SessionInner::new(session_id.to_string()))
+        let cookie_value = req
+            .cookies()
+            .get(self.manager.cookie_name())
+            .map(|x| x.value().to_string());
+        let session = match self.manager.load(cookie_value.as_deref()).await {
+            Ok(session) => session,
+            Err(err) => {
+                err.write(req, depot, res).await;
+                ctrl.skip_rest();
+                return;
             }
-            None => SessionInner::default(),
         };
-        let session = Session::new(Rc::new(RefCell::new(inner)));
         depot.inject(session.clone());
         ctrl.call_next(req, depot, res).await;
-        let inner = session.inner();
-        match inner.status {
-            SessionStatus::UnChange => {
-                if builder.auto_expire {
-                    store
-                        .expire(&inner.id.to_string(), builder.expire_time)
-                        .await
-                        .ok();
-                }
-                store.set(&inner.id.to_string(), inner.clone()).await.ok();
-            }
-            SessionStatus::Change => {
-                store.remove(&inner.id.to_string()).await.ok();
-                store.set(&inner.id.to_string(), inner.clone()).await.ok();
-                store
-                    .expire(&inner.id.to_string(), builder.expire_time)
-                    .await
-                    .ok();
-            }
-            SessionStatus::Clear => {
-                store.remove(&inner.id.to_string()).await.ok();
-            }
-            SessionStatus::Destroy => {
-                store.remove(&inner.id.to_string()).await.ok();
-            }
-            SessionStatus::Expire => {
-                store
-                    .expire(&inner.id.to_string(), builder.expire_time)
-                    .await
-                    .ok();
-            }
-        }
-        let cookie = builder.build(inner.id.clone());
-        if let Ok(cookie) = cookie.to_string().parse() {
-            res.headers_mut().insert(SET_COOKIE, cookie);
+        if let Some(cookie) = self.manager.commit(&session).await
+            && let Ok(cookie) = cookie.to_string().parse()
+        {
+            res.headers_mut().append(SET_COOKIE, cookie);
         }
     }
 }
@@ -127,6 +90,19 @@ pub trait SessionDepotExt {
 
 impl SessionDepotExt for Depot {
     fn inner_session(&self) -> Option<Session> {
-        self.obtain::<Session>().ok().map(|x| x.clone())
+        self.obtain::<Session>().ok().cloned()
+    }
+}
+
+/// Salvo Writer implementation for rsession errors
+///
+/// Lets handlers return `rsession::Error` directly. The response carries a generic
+/// message for the status; the underlying error is never sent to the client.
+#[async_trait]
+impl Writer for Error {
+    async fn write(self, _req: &mut Request, _depot: &mut Depot, res: &mut Response) {
+        let (status, message) = self.response_parts();
+        res.status_code(StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR));
+        res.render(message);
     }
 }
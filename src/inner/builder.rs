@@ -3,11 +3,16 @@
 //! This module provides types and utilities for configuring session behavior
 //! and building cookies according to the specified configuration.
 
-use cookie::{Cookie, Expiration, SameSite};
-use rand::Rng;
+use crate::Error;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use cookie::{Cookie, CookieJar, Expiration, Key, SameSite};
+use rand::TryRngCore;
+use rand::rngs::OsRng;
 use sha256::Sha256Digest;
+use std::fmt::{self, Debug, Formatter};
 use std::ops::Add;
-use std::rc::Rc;
+use std::sync::Arc;
 use time::{Duration, OffsetDateTime};
 
 /// Strategy for determining how long a session should persist
@@ -26,15 +31,51 @@ pub enum RefreshStrategy {
     PersistentStorage(Duration),
 }
 
+/// Protection applied to the session cookie value
+///
+/// Only takes effect when a secret is configured on the `SessionBuilder`;
+/// without a secret the cookie always carries the plain session ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CookieMode {
+    /// Cookie carries the raw session ID
+    Plain,
+    /// Cookie value is HMAC-signed so the client cannot forge it
+    ///
+    /// The session ID is still readable by the client
+    #[default]
+    Signed,
+    /// Cookie value is encrypted and authenticated with AEAD (AES-256-GCM)
+    ///
+    /// The client can neither read nor forge the session ID
+    Private,
+}
+
+/// What the framework integrations do when the session store fails
+///
+/// Only applies to real backend failures; a cookie pointing to an unknown session
+/// always starts a new session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackendErrorPolicy {
+    /// Reject the request with `503 Service Unavailable`
+    #[default]
+    Fail,
+    /// Continue with an ephemeral session
+    ///
+    /// The session starts empty, is never persisted and leaves the client cookie
+    /// untouched, so the user gets their stored session back once the backend recovers.
+    Ephemeral,
+}
+
 /// Strategy for generating session IDs
 ///
-/// Defines different methods for creating unique session identifiers
-#[derive(Debug, Clone)]
+/// Defines different methods for creating unique session identifiers. The random
+/// strategies draw their bytes from the operating system CSPRNG.
+#[derive(Clone)]
 pub enum RandKey {
-    /// Generate random numeric string with specified length
+    /// Generate random bytes encoded as URL-safe base64 without padding
     ///
     /// # Tuple Fields
-    /// * `0` - Length of the random numeric string to generate
+    /// * `0` - Number of random bytes to generate
     Random(usize),
     /// Generate UUID v4 compliant identifier
     ///
@@ -44,11 +85,16 @@ pub enum RandKey {
     ///
     /// Uses timestamp and random numbers for better indexing performance
     UuidV7,
-    /// Generate random string and hash it with SHA-256
+    /// Generate random bytes and hash them with SHA-256 (hex encoded)
     ///
     /// # Tuple Fields
-    /// * `0` - Length of the input random string before hashing
+    /// * `0` - Number of random bytes to hash
     RandomSha256(usize),
+    /// Generate identifiers with a user supplied function
+    ///
+    /// The function must return unique, unguessable values; it is called once
+    /// per new session
+    Custom(Arc<dyn Fn() -> String + Send + Sync>),
 }
 impl Default for RandKey {
     fn default() -> Self {
@@ -56,35 +102,54 @@ impl Default for RandKey {
     }
 }
 
+impl Debug for RandKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RandKey::Random(len) => f.debug_tuple("Random").field(len).finish(),
+            RandKey::UuidV4 => f.write_str("UuidV4"),
+            RandKey::UuidV7 => f.write_str("UuidV7"),
+            RandKey::RandomSha256(len) => f.debug_tuple("RandomSha256").field(len).finish(),
+            RandKey::Custom(_) => f.debug_tuple("Custom").finish_non_exhaustive(),
+        }
+    }
+}
+
 impl RandKey {
     /// Generates a new session ID string based on the selected strategy
+    ///
+    /// # Panics
+    /// Panics if the operating system random number generator is unavailable
     pub fn generate(&self) -> String {
-        let this = self.clone();
-        match this {
-            RandKey::Random(len) => {
-                let mut rng = rand::rng();
-                let bytes: f64 = rng.random::<f64>() % (10 * len) as f64;
-                bytes.to_string()
-            }
+        match self {
+            RandKey::Random(len) => URL_SAFE_NO_PAD.encode(Self::random_bytes(*len)),
             RandKey::UuidV4 => uuid::Uuid::new_v4().to_string(),
             RandKey::UuidV7 => uuid::Uuid::now_v7().to_string(),
-            RandKey::RandomSha256(len) => {
-                let mut rng = rand::rng();
-                let bytes: f64 = rng.random::<f64>() % (10 * len) as f64;
-                bytes.to_string().digest()
-            }
+            RandKey::RandomSha256(len) => Self::random_bytes(*len).digest(),
+            RandKey::Custom(generate) => generate(),
         }
     }
+
+    /// Reads `len` bytes from the operating system CSPRNG
+    fn random_bytes(len: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; len];
+        OsRng
+            .try_fill_bytes(&mut bytes)
+            .expect("operating system random number generator failed");
+        bytes
+    }
 }
 
 /// Builder for configuring session behavior and creating cookies
 ///
 /// Provides a fluent interface for setting session parameters and generating
-/// properly configured cookies for client-side storage.
-#[derive(Debug, Clone)]
+/// properly configured cookies for client-side storage. The `Debug` output never
+/// includes the secrets.
+#[derive(Clone)]
 pub struct SessionBuilder {
     pub key: String,
     pub secret: Option<[u8; 64]>,
+    pub previous_secrets: Vec<[u8; 64]>,
+    pub cookie_mode: CookieMode,
     pub expire_time: Duration,
     pub path: String,
     pub domain: String,
@@ -93,18 +158,43 @@ pub struct SessionBuilder {
     pub max_age: Option<Duration>,
     pub same_site: Option<SameSite>,
     pub refresh_strategy: RefreshStrategy,
-    pub rand_key: Rc<RandKey>,
+    pub rand_key: Arc<RandKey>,
     pub auto_expire: bool,
+    pub backend_error_policy: BackendErrorPolicy,
 }
 
-unsafe impl Sync for SessionBuilder {}
-unsafe impl Send for SessionBuilder {}
+impl Debug for SessionBuilder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionBuilder")
+            .field("key", &self.key)
+            .field("secret", &self.secret.map(|_| "<redacted>"))
+            .field(
+                "previous_secrets",
+                &format_args!("[<redacted>; {}]", self.previous_secrets.len()),
+            )
+            .field("cookie_mode", &self.cookie_mode)
+            .field("expire_time", &self.expire_time)
+            .field("path", &self.path)
+            .field("domain", &self.domain)
+            .field("secure", &self.secure)
+            .field("http_only", &self.http_only)
+            .field("max_age", &self.max_age)
+            .field("same_site", &self.same_site)
+            .field("refresh_strategy", &self.refresh_strategy)
+            .field("rand_key", &self.rand_key)
+            .field("auto_expire", &self.auto_expire)
+            .field("backend_error_policy", &self.backend_error_policy)
+            .finish()
+    }
+}
 
 impl Default for SessionBuilder {
     fn default() -> Self {
         SessionBuilder {
             key: "session_key".to_string(),
             secret: None,
+            previous_secrets: Vec::new(),
+            cookie_mode: CookieMode::default(),
             expire_time: Duration::days(7),
             path: "/".to_string(),
             domain: "".to_string(),
@@ -113,8 +203,9 @@ impl Default for SessionBuilder {
             max_age: None,
             same_site: None,
             refresh_strategy: RefreshStrategy::BrowserLifeCycle,
-            rand_key: Rc::new(RandKey::UuidV7),
+            rand_key: Arc::new(RandKey::UuidV7),
             auto_expire: true,
+            backend_error_policy: BackendErrorPolicy::default(),
         }
     }
 }
@@ -140,7 +231,10 @@ impl SessionBuilder {
         self.key = key.to_string();
         self
     }
-    /// Sets the secret key for session encryption (64 bytes required)
+    /// Sets the secret key used to protect session cookies (64 bytes required)
+    ///
+    /// Once a secret is set, `build` signs or encrypts the cookie value according
+    /// to the cookie mode and `decode` rejects values that fail verification.
     ///
     /// # Arguments
     /// * `secret` - 64-byte array used for cryptographic operations
@@ -152,6 +246,44 @@ impl SessionBuilder {
         self.secret = Some(<[u8; 64]>::try_from(secret.to_vec()).unwrap());
         self
     }
+    /// Adds a previous secret key that is still accepted when verifying cookies
+    ///
+    /// Use this when rotating keys: move the old key here and set the new one with
+    /// `secret`. Cookies produced under the old key keep working, and because the
+    /// middlewares send a freshly built cookie on every response, they are
+    /// transparently re-issued under the primary secret. May be called multiple times.
+    ///
+    /// # Arguments
+    /// * `secret` - 64-byte array previously passed to `secret`
+    ///
+    /// # Panics
+    /// Panics if the secret is not exactly 64 bytes long
+    pub fn previous_secret(mut self, secret: &[u8]) -> Self {
+        assert_eq!(secret.len(), 64, "secret must be 64 bytes");
+        self.previous_secrets
+            .push(<[u8; 64]>::try_from(secret.to_vec()).unwrap());
+        self
+    }
+    /// Sets how the session cookie value is protected
+    ///
+    /// Defaults to `CookieMode::Signed`. Has no effect unless a secret is set.
+    ///
+    /// # Arguments
+    /// * `cookie_mode` - Plain, signed or encrypted cookie values
+    pub fn cookie_mode(mut self, cookie_mode: CookieMode) -> Self {
+        self.cookie_mode = cookie_mode;
+        self
+    }
+    /// Sets how requests are handled when the session store fails
+    ///
+    /// Defaults to `BackendErrorPolicy::Fail`.
+    ///
+    /// # Arguments
+    /// * `policy` - Fail the request or continue with an ephemeral session
+    pub fn backend_error_policy(mut self, policy: BackendErrorPolicy) -> Self {
+        self.backend_error_policy = policy;
+        self
+    }
     /// Sets the duration after which the session expires
     ///
     /// # Arguments
@@ -222,32 +354,40 @@ impl SessionBuilder {
     }
     /// Sets the session ID generation strategy
     ///
+    /// The longest built-in ID, `RandKey::Random(1024)`, is 1366 characters; every
+    /// store except memcached accepts IDs up to that length. Memcached keys are limited
+    /// to 250 bytes including the store prefix, so `Random` above about 160 bytes
+    /// cannot be used there. `RandKey::Custom` IDs should be ASCII and stay within
+    /// the same limits.
+    ///
     /// # Arguments
     /// * `rand_key` - Strategy for generating unique session identifiers
+    ///
+    /// # Panics
+    /// Panics if a random strategy asks for fewer than 16 or more than 1024 bytes
     pub fn rand_key(mut self, rand_key: RandKey) -> Self {
         match rand_key {
-            RandKey::Random(len) => {
-                assert!(len > 64, "len must be greater than 64");
-                assert!(len < 1024, "len must be less than 1024");
-            }
-            RandKey::UuidV4 | RandKey::UuidV7 => {}
-            RandKey::RandomSha256(len) => {
-                assert!(len > 64, "len must be greater than 64");
-                assert!(len < 1024, "len must be less than 1024");
+            RandKey::Random(len) | RandKey::RandomSha256(len) => {
+                assert!(len >= 16, "len must be at least 16 bytes");
+                assert!(len <= 1024, "len must be at most 1024 bytes");
             }
+            RandKey::UuidV4 | RandKey::UuidV7 | RandKey::Custom(_) => {}
         }
-        self.rand_key = Rc::from(rand_key);
+        self.rand_key = Arc::new(rand_key);
         self
     }
 
     /// Builds a cookie with the configured parameters and given session ID
     ///
+    /// If a secret is configured the cookie value is signed or encrypted with it,
+    /// depending on the cookie mode.
+    ///
     /// # Arguments
     /// * `id` - Session ID to be stored in the cookie
     ///
     /// # Returns
     /// A configured Cookie instance ready to be sent to the client
-    pub fn build(&self, id: String) -> Cookie {
+    pub fn build(&self, id: String) -> Cookie<'static> {
         let mut cookie = Cookie::new(self.key.clone(), id);
         cookie.set_domain(self.domain.clone());
         cookie.set_path(self.path.clone());
@@ -267,6 +407,200 @@ impl SessionBuilder {
         if let Some(max_age) = self.max_age {
             cookie.set_max_age(max_age);
         }
+        let mut jar = CookieJar::new();
+        match (self.cookie_mode, &self.secret) {
+            (CookieMode::Signed, Some(secret)) => {
+                jar.signed_mut(&Key::from(secret)).add(cookie.clone());
+            }
+            (CookieMode::Private, Some(secret)) => {
+                jar.private_mut(&Key::from(secret)).add(cookie.clone());
+            }
+            _ => return cookie,
+        }
+        jar.get(&self.key).cloned().unwrap_or(cookie)
+    }
+
+    /// Builds a cookie that removes the session cookie from the client
+    ///
+    /// The cookie has an empty value and an expiration date in the past
+    ///
+    /// # Returns
+    /// A removal Cookie matching the configured name, path and domain
+    pub fn build_removal(&self) -> Cookie<'static> {
+        let mut cookie = Cookie::new(self.key.clone(), "");
+        cookie.set_domain(self.domain.clone());
+        cookie.set_path(self.path.clone());
+        cookie.set_http_only(self.http_only);
+        cookie.set_secure(self.secure);
+        cookie.set_same_site(self.same_site);
+        cookie.make_removal();
         cookie
     }
+
+    /// Extracts the session ID from a cookie value received from the client
+    ///
+    /// Without a secret the value is the session ID itself. With a secret the value
+    /// is verified (and decrypted in private mode) first, so tampered, unsigned or
+    /// undecryptable values are rejected. The primary secret is tried first,
+    /// followed by every previous secret in the order they were added.
+    ///
+    /// # Arguments
+    /// * `value` - Raw value of the session cookie
+    ///
+    /// # Returns
+    /// Ok(id) if the value is acceptable, Err(Error::Tampered) if it fails
+    /// verification or decryption with every secret
+    pub fn decode(&self, value: &str) -> Result<String, Error> {
+        let secret = match &self.secret {
+            Some(secret) if self.cookie_mode != CookieMode::Plain => secret,
+            _ => return Ok(value.to_string()),
+        };
+        std::iter::once(secret)
+            .chain(self.previous_secrets.iter())
+            .find_map(|secret| self.decode_with(value, secret))
+            .ok_or(Error::Tampered)
+    }
+
+    /// Verifies (and decrypts in private mode) a cookie value with a single secret
+    fn decode_with(&self, value: &str, secret: &[u8; 64]) -> Option<String> {
+        let mut jar = CookieJar::new();
+        jar.add_original(Cookie::new(self.key.clone(), value.to_string()));
+        let key = Key::from(secret);
+        let cookie = match self.cookie_mode {
+            CookieMode::Signed => jar.signed(&key).get(&self.key),
+            CookieMode::Private => jar.private(&key).get(&self.key),
+            CookieMode::Plain => jar.get(&self.key).cloned(),
+        };
+        cookie.map(|cookie| cookie.value().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: [u8; 64] = [7; 64];
+
+    #[test]
+    fn plain_cookie_carries_the_id() {
+        let builder = SessionBuilder::new();
+        let cookie = builder.build("abc".to_string());
+        assert_eq!(cookie.value(), "abc");
+        assert_eq!(builder.decode(cookie.value()).unwrap(), "abc");
+    }
+
+    #[test]
+    fn signed_cookie_round_trips() {
+        let builder = SessionBuilder::new().secret(&SECRET);
+        let cookie = builder.build("abc".to_string());
+        assert_ne!(cookie.value(), "abc");
+        assert_eq!(builder.decode(cookie.value()).unwrap(), "abc");
+    }
+
+    #[test]
+    fn signed_cookie_rejects_tampered_values() {
+        let builder = SessionBuilder::new().secret(&SECRET);
+        let cookie = builder.build("abc".to_string());
+        let tampered = cookie.value().replace("abc", "abd");
+        assert!(matches!(builder.decode(&tampered), Err(Error::Tampered)));
+        assert!(matches!(builder.decode("abc"), Err(Error::Tampered)));
+        let other = SessionBuilder::new().secret(&[8; 64]);
+        assert!(matches!(other.decode(cookie.value()), Err(Error::Tampered)));
+    }
+
+    #[test]
+    fn private_cookie_round_trips_without_exposing_the_id() {
+        let builder = SessionBuilder::new()
+            .secret(&SECRET)
+            .cookie_mode(CookieMode::Private);
+        let cookie = builder.build("abc".to_string());
+        assert!(!cookie.value().contains("abc"));
+        assert_eq!(builder.decode(cookie.value()).unwrap(), "abc");
+    }
+
+    #[test]
+    fn private_cookie_rejects_tampered_and_signed_values() {
+        let builder = SessionBuilder::new()
+            .secret(&SECRET)
+            .cookie_mode(CookieMode::Private);
+        let mut tampered = builder.build("abc".to_string()).value().to_string();
+        let last = if tampered.ends_with('A') { "B" } else { "A" };
+        tampered.replace_range(tampered.len() - 1.., last);
+        assert!(matches!(builder.decode(&tampered), Err(Error::Tampered)));
+        let signed = SessionBuilder::new()
+            .secret(&SECRET)
+            .build("abc".to_string());
+        assert!(matches!(
+            builder.decode(signed.value()),
+            Err(Error::Tampered)
+        ));
+    }
+
+    #[test]
+    fn previous_secrets_still_verify_after_rotation() {
+        for mode in [CookieMode::Signed, CookieMode::Private] {
+            let old = SessionBuilder::new().secret(&SECRET).cookie_mode(mode);
+            let issued = old.build("abc".to_string());
+            let rotated = SessionBuilder::new()
+                .secret(&[8; 64])
+                .previous_secret(&[9; 64])
+                .previous_secret(&SECRET)
+                .cookie_mode(mode);
+            assert_eq!(rotated.decode(issued.value()).unwrap(), "abc");
+            let reissued = rotated.build("abc".to_string());
+            assert!(matches!(old.decode(reissued.value()), Err(Error::Tampered)));
+            let dropped = SessionBuilder::new().secret(&[8; 64]).cookie_mode(mode);
+            assert!(matches!(
+                dropped.decode(issued.value()),
+                Err(Error::Tampered)
+            ));
+        }
+    }
+
+    #[test]
+    fn debug_output_redacts_the_secrets() {
+        let builder = SessionBuilder::new()
+            .secret(&[0xab; 64])
+            .previous_secret(&[0xcd; 64]);
+        let debug = format!("{builder:?}");
+        assert!(debug.contains("secret: Some(\"<redacted>\")"));
+        assert!(debug.contains("previous_secrets: [<redacted>; 1]"));
+        assert!(!debug.contains("171") && !debug.contains("205"));
+        assert!(format!("{:?}", SessionBuilder::new()).contains("secret: None"));
+    }
+
+    #[test]
+    fn rand_key_generates_ids_of_the_expected_length() {
+        assert_eq!(RandKey::Random(16).generate().len(), 22);
+        assert_eq!(RandKey::Random(1024).generate().len(), 1366);
+        assert_eq!(RandKey::RandomSha256(16).generate().len(), 64);
+        assert_eq!(RandKey::UuidV4.generate().len(), 36);
+        assert_eq!(RandKey::UuidV7.generate().len(), 36);
+        let custom = RandKey::Custom(Arc::new(|| "fixed".to_string()));
+        assert_eq!(custom.generate(), "fixed");
+    }
+
+    #[test]
+    fn rand_key_ids_are_url_safe_and_unique() {
+        let ids = [
+            RandKey::Random(32).generate(),
+            RandKey::Random(32).generate(),
+        ];
+        assert_ne!(ids[0], ids[1]);
+        assert!(
+            ids.iter()
+                .flat_map(|id| id.chars())
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        );
+        assert_ne!(
+            RandKey::RandomSha256(32).generate(),
+            RandKey::RandomSha256(32).generate()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "len must be at most 1024 bytes")]
+    fn rand_key_rejects_oversized_random_ids() {
+        let _ = SessionBuilder::new().rand_key(RandKey::Random(1025));
+    }
 }
@@ -1,19 +1,20 @@
 use crate::SessionStatus::Change;
+use crate::{Error, RandKey};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::io;
 use uuid::Uuid;
 
 /// Tracks the modification state of a session
 ///
 /// This enum is used internally to determine how to persist session changes
 /// to storage and when to update client cookies.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub enum SessionStatus {
     /// Session data has not been modified
     ///
     /// No persistence operations will be performed for this session
+    #[default]
     UnChange,
     ///
     /// Session will be persisted to storage and cookie will be updated
@@ -31,12 +32,6 @@ pub enum SessionStatus {
     /// Storage expiration timestamp will be refreshed
     Expire,
 }
-impl Default for SessionStatus {
-    fn default() -> Self {
-        SessionStatus::UnChange
-    }
-}
-
 /// Internal representation of session data
 ///
 /// Contains the session identifier, stored data, and modification status
@@ -50,6 +45,12 @@ pub struct SessionInner {
     /// Current modification status of the session
     #[serde(skip)]
     pub(crate) status: SessionStatus,
+    /// Whether the session ID should be replaced before persisting
+    #[serde(skip)]
+    pub(crate) regenerate: bool,
+    /// Whether the session is a stand-in that must never be persisted
+    #[serde(skip)]
+    pub(crate) ephemeral: bool,
 }
 
 impl Default for SessionInner {
@@ -58,6 +59,8 @@ impl Default for SessionInner {
             id: Uuid::now_v7().to_string(),
             data: HashMap::new(),
             status: SessionStatus::UnChange,
+            regenerate: false,
+            ephemeral: false,
         }
     }
 }
@@ -68,10 +71,37 @@ impl SessionInner {
     /// # Arguments
     /// * `id` - Unique identifier for the session
     pub fn new(id: String) -> Self {
-        let mut this = SessionInner::default();
-        this.id = id;
-        this.status = Change;
-        this
+        SessionInner {
+            id,
+            data: HashMap::new(),
+            status: Change,
+            regenerate: false,
+            ephemeral: false,
+        }
+    }
+    /// Creates an unchanged SessionInner for data loaded from storage
+    ///
+    /// Intended for `SessionStore` implementations returning a stored session
+    ///
+    /// # Arguments
+    /// * `id` - Identifier the session was stored under
+    /// * `data` - Serialized session data read from storage
+    pub fn loaded(id: String, data: HashMap<String, String>) -> Self {
+        SessionInner {
+            id,
+            data,
+            status: SessionStatus::UnChange,
+            regenerate: false,
+            ephemeral: false,
+        }
+    }
+    /// Returns the session identifier
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+    /// Returns true if the session is ephemeral and will not be persisted
+    pub fn is_ephemeral(&self) -> bool {
+        self.ephemeral
     }
     /// Retrieves and deserializes a value from the session
     ///
@@ -98,18 +128,12 @@ impl SessionInner {
     /// * `T` - The type of the value to store (must implement Serialize)
     ///
     /// # Returns
-    /// Ok(()) if successful, Err(io::Error) if serialization fails
-    pub fn set<T: Serialize>(&mut self, key: &str, value: T) -> Result<(), io::Error> {
-        if let Ok(s) = serde_json::to_string(&value) {
-            self.data.insert(key.to_string(), s);
-            self.status = Change;
-            Ok(())
-        } else {
-            Err(io::Error::new(
-                io::ErrorKind::Other,
-                "serde_json::to_string failed",
-            ))
-        }
+    /// Ok(()) if successful, Err(Error::Serialization) if serialization fails
+    pub fn set<T: Serialize>(&mut self, key: &str, value: T) -> Result<(), Error> {
+        self.data
+            .insert(key.to_string(), serde_json::to_string(&value)?);
+        self.status = Change;
+        Ok(())
     }
     /// Removes a key-value pair from the session
     ///
@@ -126,6 +150,47 @@ impl SessionInner {
         self.data.clear();
         self.status = SessionStatus::Clear;
     }
+    /// Destroys the session
+    ///
+    /// Clears all data and sets status to Destroy, which removes the session from
+    /// storage and expires the client cookie
+    pub fn destroy(&mut self) {
+        self.data.clear();
+        self.status = SessionStatus::Destroy;
+    }
+    /// Extends the session lifetime without rewriting its data
+    ///
+    /// Sets status to Expire unless the session is already modified, cleared or
+    /// destroyed, in which case the pending operation takes precedence
+    pub fn renew(&mut self) {
+        if let SessionStatus::UnChange = self.status {
+            self.status = SessionStatus::Expire;
+        }
+    }
+    /// Requests a new session ID while keeping the session data
+    ///
+    /// The new ID is generated when the session is persisted at the end of the
+    /// request; the data is then moved to the new ID and the old one is deleted
+    pub fn regenerate_id(&mut self) {
+        self.regenerate = true;
+    }
+    /// Applies a pending ID regeneration using the given generator
+    ///
+    /// Called by the framework middlewares right before the session is persisted.
+    /// Unmodified or renewed sessions are marked as changed so their data is
+    /// written under the new ID.
+    ///
+    /// # Returns
+    /// Some(previous_id) if the ID was replaced, None if no regeneration was pending
+    pub fn apply_regenerate(&mut self, rand_key: &RandKey) -> Option<String> {
+        if !std::mem::take(&mut self.regenerate) {
+            return None;
+        }
+        if let SessionStatus::UnChange | SessionStatus::Expire = self.status {
+            self.status = Change;
+        }
+        Some(std::mem::replace(&mut self.id, rand_key.generate()))
+    }
     /// Returns the number of key-value pairs in the session
     ///
     /// # Returns
@@ -133,4 +198,8 @@ impl SessionInner {
     pub fn len(&self) -> usize {
         self.data.len()
     }
+    /// Returns true if the session holds no key-value pairs
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
 }
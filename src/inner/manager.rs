@@ -0,0 +1,413 @@
+//! Framework-agnostic session engine
+//!
+//! This module contains the load and persist logic shared by every framework
+//! integration, so that all adapters behave identically and new adapters only
+//! need to read the cookie and write the `Set-Cookie` header.
+
+use crate::{
+    BackendErrorPolicy, Error, Session, SessionBuilder, SessionInner, SessionStatus, SessionStore,
+};
+use cookie::Cookie;
+use std::sync::Arc;
+
+/// Core session engine combining a configuration and a storage backend
+///
+/// A framework adapter calls `load` with the raw cookie value when a request
+/// arrives, hands the returned Session to the application, and calls `commit`
+/// once the response is ready. Cloning is cheap; clones share the builder and store.
+///
+/// # Type Parameters
+/// * `S` - The session storage backend implementing SessionStore
+pub struct SessionManager<S>
+where
+    S: SessionStore,
+{
+    builder: Arc<SessionBuilder>,
+    store: Arc<S>,
+}
+
+impl<S> Clone for SessionManager<S>
+where
+    S: SessionStore,
+{
+    fn clone(&self) -> Self {
+        SessionManager {
+            builder: self.builder.clone(),
+            store: self.store.clone(),
+        }
+    }
+}
+
+impl<S> SessionManager<S>
+where
+    S: SessionStore,
+{
+    /// Creates a new SessionManager
+    ///
+    /// # Arguments
+    /// * `builder` - Session configuration builder with cookie/session settings
+    /// * `store` - Session storage backend implementation
+    pub fn new(builder: SessionBuilder, store: S) -> Self {
+        SessionManager {
+            builder: Arc::new(builder),
+            store: Arc::new(store),
+        }
+    }
+    /// Returns the session configuration
+    pub fn builder(&self) -> &SessionBuilder {
+        &self.builder
+    }
+    /// Returns the storage backend
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+    /// Returns the name of the session cookie adapters should read
+    pub fn cookie_name(&self) -> &str {
+        &self.builder.key
+    }
+
+    /// Loads the session for an incoming request
+    ///
+    /// The cookie value is verified and decoded with the builder first. If it is
+    /// missing, fails verification or points to a session unknown to the store, a
+    /// new session with a freshly generated ID is started; client supplied IDs are
+    /// never adopted. The same applies to stored data that cannot be decoded and to
+    /// IDs the store rejects.
+    ///
+    /// If the store fails, the builder's `BackendErrorPolicy` decides the outcome:
+    /// the error is returned with `Fail`, and an ephemeral session is returned with
+    /// `Ephemeral`.
+    ///
+    /// # Arguments
+    /// * `cookie_value` - Raw value of the session cookie, if the request carried one
+    ///
+    /// # Returns
+    /// Ok(Session) to expose to the application for this request, or Err(Error) if
+    /// the store failed and the request should be rejected
+    pub async fn load(&self, cookie_value: Option<&str>) -> Result<Session, Error> {
+        // A tampered cookie is treated like a missing one: the client gets a new session
+        let stored = match cookie_value.and_then(|value| self.builder.decode(value).ok()) {
+            Some(id) => match self.store.get(&id).await {
+                Ok(stored) => stored,
+                // Undecodable data or a rejected ID will not heal by retrying; start over
+                Err(err) if !err.is_backend_failure() => None,
+                Err(err) => match self.builder.backend_error_policy {
+                    BackendErrorPolicy::Fail => return Err(err),
+                    BackendErrorPolicy::Ephemeral => {
+                        let mut inner = SessionInner::new(self.builder.rand_key.generate());
+                        inner.ephemeral = true;
+                        return Ok(Session::new(inner));
+                    }
+                },
+            },
+            None => None,
+        };
+        Ok(Session::new(stored.unwrap_or_else(|| {
+            SessionInner::new(self.builder.rand_key.generate())
+        })))
+    }
+
+    /// Persists the session at the end of a request
+    ///
+    /// Applies any pending ID regeneration, then writes, refreshes or deletes the
+    /// stored session according to its status. After a regeneration the old ID is
+    /// removed only once the session has been persisted under the new one.
+    ///
+    /// # Arguments
+    /// * `session` - The Session previously returned by `load`
+    ///
+    /// # Returns
+    /// The cookie to send with the response, or None if the session is ephemeral or
+    /// could not be persisted and the client should keep its current cookie
+    pub async fn commit(&self, session: &Session) -> Option<Cookie<'static>> {
+        let mut inner = session.inner();
+        if inner.ephemeral {
+            return None;
+        }
+        let old_id = inner.apply_regenerate(&self.builder.rand_key);
+        let id = inner.id.clone();
+        let persisted = match inner.status {
+            SessionStatus::UnChange => {
+                if self.builder.auto_expire {
+                    self.store.expire(&id, self.builder.expire_time).await
+                } else {
+                    Ok(())
+                }
+            }
+            SessionStatus::Change => self.store.save(&id, inner, self.builder.expire_time).await,
+            SessionStatus::Clear => self.store.remove(&id).await,
+            SessionStatus::Destroy => {
+                // Without the removal cookie the client keeps a session the store still
+                // holds, rather than believing it logged out while the session lives on
+                self.store.remove(&id).await.ok()?;
+                if let Some(old_id) = old_id {
+                    self.store.remove(&old_id).await.ok()?;
+                }
+                return Some(self.builder.build_removal());
+            }
+            SessionStatus::Expire => self.store.expire(&id, self.builder.expire_time).await,
+        };
+        persisted.ok()?;
+        // The old ID is only dropped once the data lives under the new one, so a
+        // failed write leaves the client's current cookie usable
+        if let Some(old_id) = old_id {
+            self.store.remove(&old_id).await.ok();
+        }
+        Some(self.builder.build(id))
+    }
+}
+
+#[cfg(all(test, feature = "memory"))]
+mod tests {
+    use super::*;
+    use crate::memory::MemorySessionStorage;
+    use async_trait::async_trait;
+    use parking_lot::Mutex;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use time::Duration;
+
+    /// Builds the error a failing read returns
+    type ReadError = fn() -> Error;
+
+    /// Memory store whose reads, writes, or only its data writes, can be made to fail
+    #[derive(Clone, Default)]
+    struct TestStore {
+        inner: MemorySessionStorage,
+        fail_reads: Arc<Mutex<Option<ReadError>>>,
+        fail_writes: Arc<AtomicBool>,
+        fail_saves: Arc<AtomicBool>,
+    }
+
+    impl TestStore {
+        fn fail_reads_with(&self, error: Option<ReadError>) {
+            *self.fail_reads.lock() = error;
+        }
+        fn check(&self) -> Result<(), Error> {
+            if self.fail_writes.load(Ordering::SeqCst) {
+                return Err(Error::backend("write failed"));
+            }
+            Ok(())
+        }
+        fn check_save(&self) -> Result<(), Error> {
+            if self.fail_saves.load(Ordering::SeqCst) {
+                return Err(Error::backend("save failed"));
+            }
+            self.check()
+        }
+    }
+
+    #[async_trait]
+    impl SessionStore for TestStore {
+        async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error> {
+            if let Some(error) = *self.fail_reads.lock() {
+                return Err(error());
+            }
+            self.inner.get(key).await
+        }
+        async fn set(&self, key: &str, value: SessionInner) -> Result<(), Error> {
+            self.check_save()?;
+            self.inner.set(key, value).await
+        }
+        async fn save(&self, key: &str, value: SessionInner, ttl: Duration) -> Result<(), Error> {
+            self.check_save()?;
+            self.inner.save(key, value, ttl).await
+        }
+        async fn remove(&self, key: &str) -> Result<(), Error> {
+            self.check()?;
+            self.inner.remove(key).await
+        }
+        async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), Error> {
+            self.check()?;
+            self.inner.expire(key, expire_time).await
+        }
+        async fn clear(&self) -> Result<usize, Error> {
+            self.inner.clear().await
+        }
+    }
+
+    fn manager() -> SessionManager<TestStore> {
+        SessionManager::new(SessionBuilder::new(), TestStore::default())
+    }
+
+    /// Loads a session holding `user = alice` and returns it with its cookie value
+    async fn logged_in(manager: &SessionManager<TestStore>) -> (Session, String) {
+        let session = manager.load(None).await.unwrap();
+        session.set("user", "alice").unwrap();
+        let cookie = manager.commit(&session).await.unwrap();
+        let session = manager.load(Some(cookie.value())).await.unwrap();
+        (session, cookie.value().to_string())
+    }
+
+    async fn stored(manager: &SessionManager<TestStore>, id: &str) -> Option<SessionInner> {
+        manager.store().get(id).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn changed_sessions_are_saved_and_reloaded() {
+        let manager = manager();
+        let (session, id) = logged_in(&manager).await;
+        assert_eq!(session.inner().id(), id);
+        assert_eq!(session.get::<String>("user").unwrap(), "alice");
+        assert!(matches!(session.inner().status, SessionStatus::UnChange));
+    }
+
+    #[tokio::test]
+    async fn unknown_ids_start_a_new_session() {
+        let manager = manager();
+        let session = manager.load(Some("unknown")).await.unwrap();
+        assert_ne!(session.inner().id(), "unknown");
+        assert!(session.is_empty());
+    }
+
+    #[tokio::test]
+    async fn unchanged_and_renewed_sessions_keep_their_data() {
+        let manager = manager();
+        let (session, id) = logged_in(&manager).await;
+        assert_eq!(manager.commit(&session).await.unwrap().value(), id);
+        session.renew();
+        assert_eq!(manager.commit(&session).await.unwrap().value(), id);
+        let data = stored(&manager, &id).await.unwrap();
+        assert_eq!(data.get::<String>("user").as_deref(), Some("alice"));
+    }
+
+    #[tokio::test]
+    async fn cleared_and_destroyed_sessions_are_removed() {
+        let manager = manager();
+        let (session, id) = logged_in(&manager).await;
+        session.clear();
+        assert!(manager.commit(&session).await.is_some());
+        assert!(stored(&manager, &id).await.is_none());
+
+        let (session, id) = logged_in(&manager).await;
+        session.destroy();
+        let cookie = manager.commit(&session).await.unwrap();
+        assert_eq!(cookie.value(), "");
+        assert_eq!(cookie.max_age(), Some(Duration::ZERO));
+        assert!(stored(&manager, &id).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn failed_writes_send_no_cookie() {
+        let manager = manager();
+        let session = manager.load(None).await.unwrap();
+        session.set("user", "alice").unwrap();
+        manager.store().fail_writes.store(true, Ordering::SeqCst);
+        assert!(manager.commit(&session).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn failed_destroy_keeps_the_cookie() {
+        let manager = manager();
+        let (session, id) = logged_in(&manager).await;
+        session.destroy();
+        manager.store().fail_writes.store(true, Ordering::SeqCst);
+        assert!(manager.commit(&session).await.is_none());
+        manager.store().fail_writes.store(false, Ordering::SeqCst);
+        let session = manager.load(Some(&id)).await.unwrap();
+        assert_eq!(session.get::<String>("user").unwrap(), "alice");
+    }
+
+    #[tokio::test]
+    async fn failing_backend_fails_the_request_by_default() {
+        let manager = manager();
+        let (_, id) = logged_in(&manager).await;
+        manager
+            .store()
+            .fail_reads_with(Some(|| Error::backend("read failed")));
+        let err = manager.load(Some(&id)).await.unwrap_err();
+        assert!(err.is_backend_failure());
+        // Requests without a cookie never reach the store
+        assert!(manager.load(None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn failing_backend_yields_an_ephemeral_session_that_keeps_the_cookie() {
+        let manager = SessionManager::new(
+            SessionBuilder::new().backend_error_policy(BackendErrorPolicy::Ephemeral),
+            TestStore::default(),
+        );
+        let (_, id) = logged_in(&manager).await;
+        manager
+            .store()
+            .fail_reads_with(Some(|| Error::pool("no connection")));
+        let session = manager.load(Some(&id)).await.unwrap();
+        assert!(session.inner().is_ephemeral());
+        assert!(session.is_empty());
+        assert_ne!(session.inner().id(), id);
+        session.set("user", "bob").unwrap();
+        assert!(manager.commit(&session).await.is_none());
+
+        manager.store().fail_reads_with(None);
+        let session = manager.load(Some(&id)).await.unwrap();
+        assert_eq!(session.get::<String>("user").unwrap(), "alice");
+    }
+
+    #[tokio::test]
+    async fn unreadable_sessions_start_a_new_session() {
+        let manager = manager();
+        let (_, id) = logged_in(&manager).await;
+        let errors: [ReadError; 2] = [
+            || Error::InvalidId("bad".to_string()),
+            || Error::Serialization(serde_json::from_str::<u8>("").unwrap_err()),
+        ];
+        for error in errors {
+            manager.store().fail_reads_with(Some(error));
+            let session = manager.load(Some(&id)).await.unwrap();
+            assert!(!session.inner().is_ephemeral());
+            assert!(session.is_empty());
+            assert_ne!(session.inner().id(), id);
+        }
+    }
+
+    #[tokio::test]
+    async fn tampered_cookies_start_a_new_session() {
+        let manager =
+            SessionManager::new(SessionBuilder::new().secret(&[7; 64]), TestStore::default());
+        let session = manager.load(None).await.unwrap();
+        session.set("user", "alice").unwrap();
+        let cookie = manager.commit(&session).await.unwrap();
+        let id = session.inner().id().to_string();
+        assert_eq!(manager.load(Some(cookie.value())).await.unwrap().len(), 1);
+        let session = manager.load(Some(&id)).await.unwrap();
+        assert_ne!(session.inner().id(), id);
+        assert!(session.is_empty());
+    }
+
+    #[tokio::test]
+    async fn regenerate_moves_the_data_to_a_new_id() {
+        let manager = manager();
+        let (session, old_id) = logged_in(&manager).await;
+        session.regenerate_id();
+        let cookie = manager.commit(&session).await.unwrap();
+        assert_ne!(cookie.value(), old_id);
+        assert!(stored(&manager, &old_id).await.is_none());
+        let data = stored(&manager, cookie.value()).await.unwrap();
+        assert_eq!(data.get::<String>("user").as_deref(), Some("alice"));
+        let session = manager.load(Some(cookie.value())).await.unwrap();
+        assert_eq!(session.get::<String>("user").unwrap(), "alice");
+    }
+
+    #[tokio::test]
+    async fn regenerate_keeps_the_old_id_if_saving_fails() {
+        let manager = manager();
+        let (session, old_id) = logged_in(&manager).await;
+        session.regenerate_id();
+        manager.store().fail_saves.store(true, Ordering::SeqCst);
+        assert!(manager.commit(&session).await.is_none());
+        manager.store().fail_saves.store(false, Ordering::SeqCst);
+        let session = manager.load(Some(&old_id)).await.unwrap();
+        assert_eq!(session.inner().id(), old_id);
+        assert_eq!(session.get::<String>("user").unwrap(), "alice");
+    }
+
+    #[tokio::test]
+    async fn regenerate_then_destroy_removes_both_ids() {
+        let manager = manager();
+        let (session, old_id) = logged_in(&manager).await;
+        session.regenerate_id();
+        session.destroy();
+        assert_eq!(manager.commit(&session).await.unwrap().value(), "");
+        assert!(stored(&manager, &old_id).await.is_none());
+        assert_eq!(manager.store().inner.len().await, 0);
+    }
+}
@@ -1,7 +1,10 @@
 pub mod builder;
+#[allow(clippy::module_inception)]
 pub mod inner;
+pub mod manager;
 pub mod session;
 
 pub use builder::*;
 pub use inner::*;
+pub use manager::*;
 pub use session::*;
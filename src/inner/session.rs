@@ -1,31 +1,23 @@
-use crate::SessionInner;
-use std::cell::RefCell;
-use std::io;
-use std::rc::Rc;
+use crate::{Error, SessionInner};
+use parking_lot::RwLock;
+use std::sync::Arc;
 
 /// Thread-safe wrapper around session data with interior mutability
 ///
 /// This struct provides the public API for session manipulation while ensuring
-/// safe concurrent access through `Rc<RefCell<SessionInner>>`.
+/// safe concurrent access through `Arc<RwLock<SessionInner>>`. Clones share the
+/// same data, so a session can be moved into spawned tasks or held across
+/// `.await` points on a multi-threaded runtime.
 #[derive(Clone, Debug)]
-pub struct Session(pub(crate) Rc<RefCell<SessionInner>>);
+pub struct Session(pub(crate) Arc<RwLock<SessionInner>>);
 
-/// Unsafe Send implementation for Session
-///
-/// Safety: While `RefCell` is not thread-safe, this implementation is valid
-/// because the session system ensures proper synchronization in async contexts.
-unsafe impl Send for Session {}
-/// Unsafe Sync implementation for Session
-///
-/// Safety: See Send implementation for safety justification
-unsafe impl Sync for Session {}
 impl Session {
-    /// Creates a new Session from an `Rc<RefCell<SessionInner>>`
+    /// Creates a new Session wrapping the given session data
     ///
     /// # Arguments
-    /// * `inner` - Shared reference to the underlying session data
-    pub fn new(inner: Rc<RefCell<SessionInner>>) -> Self {
-        Session(inner)
+    /// * `inner` - The underlying session data
+    pub fn new(inner: SessionInner) -> Self {
+        Session(Arc::new(RwLock::new(inner)))
     }
     /// Retrieves and deserializes a value from the session
     ///
@@ -36,15 +28,16 @@ impl Session {
     /// * `T` - The type to deserialize the value into
     ///
     /// # Returns
-    /// Ok(T) if the key exists and deserialization succeeds, Err(io::Error) otherwise
-    pub fn get<T>(&self, key: &str) -> Result<T, io::Error>
+    /// Ok(T) if the key exists and deserialization succeeds, Err(Error::NotFound) if
+    /// the key does not exist, Err(Error::Serialization) if the value does not
+    /// deserialize into `T`
+    pub fn get<T>(&self, key: &str) -> Result<T, Error>
     where
         T: serde::de::DeserializeOwned,
     {
-        self.0
-            .borrow()
-            .get::<T>(key)
-            .ok_or(io::Error::new(io::ErrorKind::Other, "get session error"))
+        let inner = self.0.read();
+        let value = inner.data.get(key).ok_or(Error::NotFound)?;
+        Ok(serde_json::from_str::<T>(value)?)
     }
     /// Serializes and stores a value in the session
     ///
@@ -56,38 +49,70 @@ impl Session {
     /// * `T` - The type of the value to store (must implement Serialize)
     ///
     /// # Returns
-    /// Ok(()) if successful, Err(io::Error) if serialization fails
-    pub fn set<T>(&self, key: &str, value: T) -> Result<(), io::Error>
+    /// Ok(()) if successful, Err(Error::Serialization) if serialization fails
+    pub fn set<T>(&self, key: &str, value: T) -> Result<(), Error>
     where
         T: serde::Serialize,
     {
-        self.0.borrow_mut().set(key, value)
+        self.0.write().set(key, value)
     }
     /// Removes a key-value pair from the session
     ///
     /// # Arguments
     /// * `key` - The key to remove from the session data
     pub fn remove(&self, key: &str) {
-        self.0.borrow_mut().remove(key)
+        self.0.write().remove(key)
     }
     /// Clears all data from the session
     ///
     /// This sets the session status to Clear, triggering full removal from storage
     pub fn clear(&self) {
-        self.0.borrow_mut().clear()
+        self.0.write().clear()
+    }
+    /// Destroys the session
+    ///
+    /// The session is deleted from storage and the client cookie is replaced by an
+    /// expired one, e.g. on logout
+    pub fn destroy(&self) {
+        self.0.write().destroy()
+    }
+    /// Extends the session lifetime without rewriting its data
+    ///
+    /// Refreshes the storage expiration, e.g. for keep-alive requests
+    pub fn renew(&self) {
+        self.0.write().renew()
+    }
+    /// Replaces the session ID while keeping the session data
+    ///
+    /// Call this after privilege changes such as login to prevent session fixation.
+    /// The middleware generates the new ID with the configured `RandKey`, moves the
+    /// stored data to it, deletes the old key and sends the new cookie.
+    pub fn regenerate_id(&self) {
+        self.0.write().regenerate_id()
     }
     /// Returns the number of key-value pairs in the session
     ///
     /// # Returns
     /// The count of entries in the session data map
     pub fn len(&self) -> usize {
-        self.0.borrow().len()
+        self.0.read().len()
+    }
+    /// Returns true if the session holds no key-value pairs
+    pub fn is_empty(&self) -> bool {
+        self.0.read().is_empty()
+    }
+    /// Returns true if the session is ephemeral
+    ///
+    /// Ephemeral sessions stand in for the real session while the session store is
+    /// failing; changes made to them are discarded at the end of the request.
+    pub fn is_ephemeral(&self) -> bool {
+        self.0.read().is_ephemeral()
     }
     /// Returns a cloned copy of the inner SessionInner data
     ///
     /// # Returns
     /// A clone of the underlying SessionInner containing all session data
     pub fn inner(&self) -> SessionInner {
-        self.0.borrow().clone()
+        self.0.read().clone()
     }
 }
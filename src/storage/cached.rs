@@ -0,0 +1,603 @@
+//! Two-tier cached session storage
+//!
+//! This module provides a wrapper placing a bounded in-process LRU cache in front of
+//! any other SessionStore, so hot sessions are served without a round trip to the
+//! remote backend. Writes go through to the wrapped store before updating the cache.
+
+use crate::storage::{IdPage, SessionStore};
+use crate::{Error, SessionInner};
+use async_trait::async_trait;
+use lru::LruCache;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::Instant;
+use time::Duration;
+
+/// A cached copy of a stored session
+struct CacheEntry {
+    /// Session data as last read from or written to the wrapped store
+    data: HashMap<String, String>,
+    /// Point in time after which the copy must be re-read from the wrapped store
+    stale_at: Instant,
+    /// When this process last set the session's expiry in the wrapped store, and to what
+    refreshed: Option<(Instant, Duration)>,
+}
+
+/// Read misses and writes of one session waiting for the wrapped store
+#[derive(Default)]
+struct PendingOperations {
+    /// Number of reads and writes in flight
+    operations: usize,
+    /// Number of writes or invalidations of the session since the first of them started
+    writes: u64,
+}
+
+/// Cached sessions together with the operations in flight
+struct CacheState {
+    /// Bounded cache of recently used sessions
+    entries: LruCache<String, CacheEntry>,
+    /// Sessions being read from or written to the wrapped store
+    ///
+    /// A read only caches its result if the session was not written while it was
+    /// waiting, so it cannot replace newer data or revive a removed session. A write
+    /// only caches its data if no other write of the session started meanwhile: the
+    /// order in which the wrapped store applied overlapping writes is unknown, so the
+    /// cached copy is dropped instead. Operations on other sessions do not interfere.
+    pending: HashMap<String, PendingOperations>,
+}
+
+impl CacheState {
+    /// Caches a session after a write
+    fn store(&mut self, key: &str, entry: CacheEntry) {
+        self.written(key);
+        self.entries.put(key.to_string(), entry);
+    }
+    /// Drops the cached copy of a session
+    fn invalidate(&mut self, key: &str) {
+        self.written(key);
+        self.entries.pop(key);
+    }
+    /// Drops every cached session
+    fn clear(&mut self) {
+        for pending in self.pending.values_mut() {
+            pending.writes += 1;
+        }
+        self.entries.clear();
+    }
+    /// Records a write to a session for the operations on it in flight
+    fn written(&mut self, key: &str) {
+        if let Some(pending) = self.pending.get_mut(key) {
+            pending.writes += 1;
+        }
+    }
+}
+
+/// A read miss or write in flight, unregistered when dropped
+struct InFlight {
+    cache: Arc<Mutex<CacheState>>,
+    key: String,
+    /// Writes counted for the session when the operation started, including itself
+    writes: u64,
+}
+
+impl InFlight {
+    /// Registers an operation on `key`; `state` must be the locked content of `cache`
+    ///
+    /// A write counts itself, so that reads and writes of the session already in
+    /// flight do not cache their outcome.
+    fn start(
+        cache: &Arc<Mutex<CacheState>>,
+        state: &mut CacheState,
+        key: &str,
+        write: bool,
+    ) -> Self {
+        let pending = state.pending.entry(key.to_string()).or_default();
+        pending.operations += 1;
+        if write {
+            pending.writes += 1;
+        }
+        InFlight {
+            cache: cache.clone(),
+            key: key.to_string(),
+            writes: pending.writes,
+        }
+    }
+    /// Returns true if the session was not written since the operation started
+    fn unchanged(&self, cache: &CacheState) -> bool {
+        cache
+            .pending
+            .get(&self.key)
+            .is_some_and(|pending| pending.writes == self.writes)
+    }
+    /// Caches what a write stored, or drops the cached copy if writes overlapped
+    ///
+    /// # Arguments
+    /// * `entry` - The session written, or None if the write removed it
+    fn complete_write(self, entry: Option<CacheEntry>) {
+        let mut cache = self.cache.lock();
+        match entry {
+            Some(entry) if self.unchanged(&cache) => cache.store(&self.key, entry),
+            _ => cache.invalidate(&self.key),
+        }
+    }
+    /// Caches what the wrapped store returned, unless the session was written meanwhile
+    ///
+    /// # Arguments
+    /// * `entry` - The session read, or None if the wrapped store does not have it
+    fn complete_read(self, entry: Option<CacheEntry>) {
+        let mut cache = self.cache.lock();
+        if !self.unchanged(&cache) {
+            return;
+        }
+        match entry {
+            Some(entry) if !cache.entries.contains(&self.key) => {
+                cache.entries.put(self.key.clone(), entry);
+            }
+            Some(_) => {}
+            None => {
+                cache.entries.pop(&self.key);
+            }
+        }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let mut cache = self.cache.lock();
+        if let Some(pending) = cache.pending.get_mut(&self.key) {
+            pending.operations -= 1;
+            if pending.operations == 0 {
+                cache.pending.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// Session storage wrapper caching sessions of another store in process memory
+///
+/// Cached sessions are served for at most the configured TTL, and never past the
+/// expiry set through this wrapper. Changes made through other processes sharing the
+/// wrapped store are only observed once the local copy turns stale, so the TTL bounds
+/// how long another node may see outdated data; keep it short (a few seconds) when
+/// several instances serve the same users.
+///
+/// Refreshing the expiry of a cached session with the same duration is sent to the
+/// wrapped store at most once per cache TTL, so the stored expiry may lag behind the
+/// requested one by up to the cache TTL. Use `save` when the stored expiry must match
+/// exactly, as it always reaches the wrapped store.
+///
+/// # Type Parameters
+/// * `S` - The wrapped session storage backend
+#[derive(Clone)]
+pub struct CachedSessionStore<S>
+where
+    S: SessionStore,
+{
+    /// Wrapped storage backend holding the authoritative copy
+    inner: S,
+    /// Bounded cache of recently used sessions, shared by all clones
+    cache: Arc<Mutex<CacheState>>,
+    /// Maximum time a session is served from the cache
+    ttl: Duration,
+}
+
+impl<S> CachedSessionStore<S>
+where
+    S: SessionStore,
+{
+    /// Creates a new CachedSessionStore
+    ///
+    /// # Arguments
+    /// * `inner` - Storage backend to cache
+    /// * `capacity` - Maximum number of sessions kept in memory
+    /// * `ttl` - Maximum time a session is served from the cache
+    ///
+    /// # Panics
+    /// Panics if the TTL is not positive
+    pub fn new(inner: S, capacity: NonZeroUsize, ttl: Duration) -> Self {
+        assert!(ttl.is_positive(), "ttl must be positive");
+        CachedSessionStore {
+            inner,
+            cache: Arc::new(Mutex::new(CacheState {
+                entries: LruCache::new(capacity),
+                pending: HashMap::new(),
+            })),
+            ttl,
+        }
+    }
+    /// Returns the wrapped storage backend
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+    /// Drops the cached copy of a session, forcing the next read to hit the wrapped store
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to invalidate
+    pub fn invalidate(&self, key: &str) {
+        self.cache.lock().invalidate(key);
+    }
+
+    /// Registers a write of `key` before it is sent to the wrapped store
+    fn start_write(&self, key: &str) -> InFlight {
+        let mut cache = self.cache.lock();
+        InFlight::start(&self.cache, &mut cache, key, true)
+    }
+
+    /// Returns the point in time `duration` from now, saturating far in the future
+    fn after(duration: Duration) -> Instant {
+        let now = Instant::now();
+        now.checked_add(duration.unsigned_abs())
+            .unwrap_or(now + std::time::Duration::from_secs(60 * 60 * 24 * 365))
+    }
+}
+
+#[async_trait]
+impl<S> SessionStore for CachedSessionStore<S>
+where
+    S: SessionStore,
+{
+    /// Retrieves a session from the cache, or from the wrapped store on a miss
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to look up
+    ///
+    /// # Returns
+    /// Ok(Some(SessionInner)) if found, Ok(None) if the wrapped store has no such session,
+    /// Err(Error) if the wrapped store fails
+    async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error> {
+        let (miss, refreshed) = {
+            let mut cache = self.cache.lock();
+            let refreshed = match cache.entries.get(key) {
+                Some(entry) if entry.stale_at > Instant::now() => {
+                    return Ok(Some(SessionInner::loaded(
+                        key.to_string(),
+                        entry.data.clone(),
+                    )));
+                }
+                Some(_) => cache.entries.pop(key).and_then(|entry| entry.refreshed),
+                None => None,
+            };
+            (
+                InFlight::start(&self.cache, &mut cache, key, false),
+                refreshed,
+            )
+        };
+        let value = self.inner.get(key).await?;
+        miss.complete_read(value.as_ref().map(|value| CacheEntry {
+            data: value.data.clone(),
+            stale_at: Self::after(self.ttl),
+            refreshed,
+        }));
+        Ok(value)
+    }
+
+    /// Writes a session through to the wrapped store, then caches it
+    ///
+    /// If another write of the session overlapped with this one, the cached copy is
+    /// dropped instead, since the wrapped store may have applied them in either order.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    ///
+    /// # Returns
+    /// Ok(()) if the wrapped store accepted the write, Err(Error) otherwise
+    async fn set(&self, key: &str, value: SessionInner) -> Result<(), Error> {
+        let data = value.data.clone();
+        let write = self.start_write(key);
+        if let Err(err) = self.inner.set(key, value).await {
+            write.complete_write(None);
+            return Err(err);
+        }
+        write.complete_write(Some(CacheEntry {
+            data,
+            stale_at: Self::after(self.ttl),
+            refreshed: None,
+        }));
+        Ok(())
+    }
+
+    /// Saves a session with its expiry through the wrapped store, then caches it
+    ///
+    /// Overlapping writes of the session drop the cached copy, as for `set`.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    /// * `ttl` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if the wrapped store accepted the write, Err(Error) otherwise
+    async fn save(&self, key: &str, value: SessionInner, ttl: Duration) -> Result<(), Error> {
+        let data = value.data.clone();
+        let write = self.start_write(key);
+        if let Err(err) = self.inner.save(key, value, ttl).await {
+            write.complete_write(None);
+            return Err(err);
+        }
+        write.complete_write(ttl.is_positive().then(|| CacheEntry {
+            data,
+            stale_at: Self::after(self.ttl.min(ttl)),
+            refreshed: Some((Instant::now(), ttl)),
+        }));
+        Ok(())
+    }
+
+    /// Removes a session from the cache and the wrapped store
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to remove
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if the wrapped store fails
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        self.invalidate(key);
+        let result = self.inner.remove(key).await;
+        // Reads that started before the removal completed must not cache the old data
+        self.invalidate(key);
+        result
+    }
+
+    /// Sets expiration time for a session in the wrapped store
+    ///
+    /// If the session is cached and this process set the same expiry duration within
+    /// the cache TTL, the call is answered locally without reaching the wrapped store.
+    /// The cached copy is kept no longer than the new expiry.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to update
+    /// * `expire_time` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if the wrapped store fails
+    async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), Error> {
+        if expire_time.is_positive()
+            && let Some(entry) = self.cache.lock().entries.peek(key)
+            && entry.stale_at > Instant::now()
+            && entry.refreshed.is_some_and(|(at, duration)| {
+                duration == expire_time && at.elapsed() < self.ttl.unsigned_abs()
+            })
+        {
+            return Ok(());
+        }
+        if let Err(err) = self.inner.expire(key, expire_time).await {
+            self.invalidate(key);
+            return Err(err);
+        }
+        if !expire_time.is_positive() {
+            self.invalidate(key);
+        } else if let Some(entry) = self.cache.lock().entries.peek_mut(key) {
+            entry.stale_at = entry.stale_at.min(Self::after(expire_time));
+            entry.refreshed = Some((Instant::now(), expire_time));
+        }
+        Ok(())
+    }
+
+    /// Empties the cache and removes all sessions from the wrapped store
+    ///
+    /// # Returns
+    /// Ok(count) as reported by the wrapped store, Err(Error) if it fails
+    async fn clear(&self) -> Result<usize, Error> {
+        self.cache.lock().clear();
+        let result = self.inner.clear().await;
+        self.cache.lock().clear();
+        result
+    }
+
+    /// Counts the sessions of the wrapped store
+    ///
+    /// # Returns
+    /// Ok(count) with the number of sessions, Err(Error) if the wrapped store fails
+    async fn count(&self) -> Result<usize, Error> {
+        self.inner.count().await
+    }
+
+    /// Lists one page of session IDs from the wrapped store
+    ///
+    /// # Arguments
+    /// * `cursor` - Cursor returned by the previous call, None for the first page
+    /// * `limit` - Approximate number of IDs to return
+    ///
+    /// # Returns
+    /// Ok(IdPage) with the IDs and the next cursor, Err(Error) if the wrapped store fails
+    async fn ids(&self, cursor: Option<&str>, limit: usize) -> Result<IdPage, Error> {
+        self.inner.ids(cursor, limit).await
+    }
+
+    /// Returns the remaining lifetime of a session from the wrapped store
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to look up
+    ///
+    /// # Returns
+    /// Ok(Some(duration)) if the session expires, Ok(None) if it never expires,
+    /// Err(Error::NotFound) if it does not exist
+    async fn ttl(&self, key: &str) -> Result<Option<Duration>, Error> {
+        self.inner.ttl(key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::Notify;
+
+    /// Signalled when a gated call is entered, and awaited before it returns
+    type Gate = (Arc<Notify>, Arc<Notify>);
+
+    /// Store answering every read with an empty session and counting `expire` calls
+    ///
+    /// Reads wait for `release` once `entered` has been signalled, so tests can
+    /// interleave writes with a read in flight. The first write waits the same way
+    /// on `write_gate`.
+    #[derive(Clone, Default)]
+    struct TestStore {
+        expires: Arc<AtomicUsize>,
+        gate: Option<Gate>,
+        write_gate: Arc<Mutex<Option<Gate>>>,
+    }
+
+    #[async_trait]
+    impl SessionStore for TestStore {
+        async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error> {
+            if let Some((entered, release)) = &self.gate {
+                entered.notify_one();
+                release.notified().await;
+            }
+            Ok(Some(SessionInner::loaded(key.to_string(), HashMap::new())))
+        }
+        async fn set(&self, _: &str, _: SessionInner) -> Result<(), Error> {
+            let gate = self.write_gate.lock().take();
+            if let Some((entered, release)) = gate {
+                entered.notify_one();
+                release.notified().await;
+            }
+            Ok(())
+        }
+        async fn remove(&self, _: &str) -> Result<(), Error> {
+            Ok(())
+        }
+        async fn expire(&self, _: &str, _: Duration) -> Result<(), Error> {
+            self.expires.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+        async fn clear(&self) -> Result<usize, Error> {
+            Ok(0)
+        }
+    }
+
+    fn cached(inner: TestStore) -> CachedSessionStore<TestStore> {
+        CachedSessionStore::new(inner, NonZeroUsize::new(8).unwrap(), Duration::minutes(1))
+    }
+
+    fn session(value: i32) -> SessionInner {
+        let mut session = SessionInner::new("a".to_string());
+        session.set("k", value).unwrap();
+        session
+    }
+
+    #[tokio::test]
+    async fn repeated_expire_reaches_inner_store_once_per_ttl() {
+        let inner = TestStore::default();
+        let store = cached(inner.clone());
+        store.get("a").await.unwrap();
+        for _ in 0..5 {
+            store.expire("a", Duration::hours(1)).await.unwrap();
+        }
+        assert_eq!(inner.expires.load(Ordering::SeqCst), 1);
+        store.expire("a", Duration::hours(2)).await.unwrap();
+        store.expire("b", Duration::hours(1)).await.unwrap();
+        assert_eq!(inner.expires.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn expire_reaches_inner_store_again_after_the_cache_ttl() {
+        let inner = TestStore::default();
+        let store = CachedSessionStore::new(
+            inner.clone(),
+            NonZeroUsize::new(8).unwrap(),
+            Duration::milliseconds(50),
+        );
+        store.get("a").await.unwrap();
+        store.expire("a", Duration::hours(1)).await.unwrap();
+        store.expire("a", Duration::hours(1)).await.unwrap();
+        assert_eq!(inner.expires.load(Ordering::SeqCst), 1);
+        tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+        store.get("a").await.unwrap();
+        store.expire("a", Duration::hours(1)).await.unwrap();
+        store.expire("a", Duration::hours(1)).await.unwrap();
+        assert_eq!(inner.expires.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn overlapping_writes_drop_the_cached_copy() {
+        let entered = Arc::new(Notify::new());
+        let release = Arc::new(Notify::new());
+        let inner = TestStore::default();
+        *inner.write_gate.lock() = Some((entered.clone(), release.clone()));
+        let store = cached(inner);
+        let first = tokio::spawn({
+            let store = store.clone();
+            async move { store.set("a", session(1)).await }
+        });
+        entered.notified().await;
+        store.set("a", session(2)).await.unwrap();
+        release.notify_one();
+        first.await.unwrap().unwrap();
+        {
+            let cache = store.cache.lock();
+            assert!(!cache.entries.contains("a"));
+            assert!(cache.pending.is_empty());
+        }
+        store.set("a", session(3)).await.unwrap();
+        let cached = store.cache.lock().entries.peek("a").unwrap().data.clone();
+        assert_eq!(cached.get("k").map(String::as_str), Some("3"));
+    }
+
+    #[tokio::test]
+    async fn read_miss_does_not_replace_a_concurrent_write() {
+        let entered = Arc::new(Notify::new());
+        let release = Arc::new(Notify::new());
+        let store = cached(TestStore {
+            gate: Some((entered.clone(), release.clone())),
+            ..TestStore::default()
+        });
+        let read = tokio::spawn({
+            let store = store.clone();
+            async move { store.get("a").await }
+        });
+        entered.notified().await;
+        store.set("a", session(1)).await.unwrap();
+        release.notify_one();
+        read.await.unwrap().unwrap();
+        let cached = store.cache.lock().entries.peek("a").unwrap().data.clone();
+        assert_eq!(cached.get("k").map(String::as_str), Some("1"));
+    }
+
+    #[tokio::test]
+    async fn read_miss_does_not_revive_a_concurrent_removal() {
+        let entered = Arc::new(Notify::new());
+        let release = Arc::new(Notify::new());
+        let store = cached(TestStore {
+            gate: Some((entered.clone(), release.clone())),
+            ..TestStore::default()
+        });
+        let read = tokio::spawn({
+            let store = store.clone();
+            async move { store.get("a").await }
+        });
+        entered.notified().await;
+        store.remove("a").await.unwrap();
+        release.notify_one();
+        read.await.unwrap().unwrap();
+        assert!(store.cache.lock().entries.peek("a").is_none());
+    }
+
+    #[tokio::test]
+    async fn writes_to_other_sessions_do_not_stop_a_read_miss_from_caching() {
+        let entered = Arc::new(Notify::new());
+        let release = Arc::new(Notify::new());
+        let store = cached(TestStore {
+            gate: Some((entered.clone(), release.clone())),
+            ..TestStore::default()
+        });
+        let read = tokio::spawn({
+            let store = store.clone();
+            async move { store.get("a").await }
+        });
+        entered.notified().await;
+        store
+            .set("b", SessionInner::new("b".to_string()))
+            .await
+            .unwrap();
+        store.invalidate("c");
+        release.notify_one();
+        read.await.unwrap().unwrap();
+        let cache = store.cache.lock();
+        assert!(cache.entries.contains("a"));
+        assert!(cache.pending.is_empty());
+    }
+}
@@ -0,0 +1,612 @@
+//! Failover session storage
+//!
+//! This module provides a wrapper combining a primary and a secondary SessionStore.
+//! Reads are served by the primary and fall back to the secondary when the primary
+//! fails, so a backend outage does not silently log every user out.
+
+use crate::storage::{IdPage, SessionStore};
+use crate::{Error, SessionInner};
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use time::Duration;
+
+/// How FailoverSessionStore distributes writes between its backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WritePolicy {
+    /// Write to both backends; succeeds if at least one of them accepts the write
+    #[default]
+    Both,
+    /// Write to the primary only; the secondary is only read from
+    PrimaryOnly,
+    /// Write to the primary, and to the secondary only when the primary fails
+    SecondaryOnFailure,
+}
+
+/// Snapshot of the health of both backends of a FailoverSessionStore
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FailoverHealth {
+    /// Whether the last operation on the primary succeeded
+    pub primary_healthy: bool,
+    /// Whether the last operation on the secondary succeeded
+    pub secondary_healthy: bool,
+    /// Number of failed operations on the primary
+    pub primary_failures: u64,
+    /// Number of failed operations on the secondary
+    pub secondary_failures: u64,
+    /// Number of operations served by the secondary because the primary failed
+    pub failovers: u64,
+}
+
+/// Shared health counters
+#[derive(Debug)]
+struct HealthState {
+    primary_healthy: AtomicBool,
+    secondary_healthy: AtomicBool,
+    primary_failures: AtomicU64,
+    secondary_failures: AtomicU64,
+    failovers: AtomicU64,
+}
+
+/// Session storage wrapper failing over from a primary to a secondary backend
+///
+/// Every operation records the outcome for each backend it touched; `health` returns
+/// the current state for monitoring. Removals are always applied to both backends so
+/// that a destroyed session cannot come back from the secondary after a failover, and
+/// they fail if a backend that may still hold the session could not be reached, so
+/// the session cannot come back from the primary once it recovers either.
+///
+/// # Type Parameters
+/// * `A` - The primary session storage backend
+/// * `B` - The secondary session storage backend
+#[derive(Clone)]
+pub struct FailoverSessionStore<A, B>
+where
+    A: SessionStore,
+    B: SessionStore,
+{
+    primary: A,
+    secondary: B,
+    policy: WritePolicy,
+    health: Arc<HealthState>,
+}
+
+impl<A, B> FailoverSessionStore<A, B>
+where
+    A: SessionStore,
+    B: SessionStore,
+{
+    /// Creates a new FailoverSessionStore
+    ///
+    /// # Arguments
+    /// * `primary` - Backend serving requests while it is healthy
+    /// * `secondary` - Backend used when the primary fails
+    /// * `policy` - How writes are distributed between the backends
+    pub fn new(primary: A, secondary: B, policy: WritePolicy) -> Self {
+        FailoverSessionStore {
+            primary,
+            secondary,
+            policy,
+            health: Arc::new(HealthState {
+                primary_healthy: AtomicBool::new(true),
+                secondary_healthy: AtomicBool::new(true),
+                primary_failures: AtomicU64::new(0),
+                secondary_failures: AtomicU64::new(0),
+                failovers: AtomicU64::new(0),
+            }),
+        }
+    }
+    /// Returns the primary backend
+    pub fn primary(&self) -> &A {
+        &self.primary
+    }
+    /// Returns the secondary backend
+    pub fn secondary(&self) -> &B {
+        &self.secondary
+    }
+    /// Returns the configured write policy
+    pub fn policy(&self) -> WritePolicy {
+        self.policy
+    }
+    /// Returns a snapshot of the health of both backends
+    pub fn health(&self) -> FailoverHealth {
+        FailoverHealth {
+            primary_healthy: self.health.primary_healthy.load(Ordering::Relaxed),
+            secondary_healthy: self.health.secondary_healthy.load(Ordering::Relaxed),
+            primary_failures: self.health.primary_failures.load(Ordering::Relaxed),
+            secondary_failures: self.health.secondary_failures.load(Ordering::Relaxed),
+            failovers: self.health.failovers.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Records the outcome of an operation on the primary and passes it through
+    fn track_primary<T>(&self, result: Result<T, Error>) -> Result<T, Error> {
+        let failed = result.as_ref().is_err_and(Error::is_backend_failure);
+        self.health
+            .primary_healthy
+            .store(!failed, Ordering::Relaxed);
+        if failed {
+            self.health.primary_failures.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+    /// Records the outcome of an operation on the secondary and passes it through
+    fn track_secondary<T>(&self, result: Result<T, Error>) -> Result<T, Error> {
+        let failed = result.as_ref().is_err_and(Error::is_backend_failure);
+        self.health
+            .secondary_healthy
+            .store(!failed, Ordering::Relaxed);
+        if failed {
+            self.health
+                .secondary_failures
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+    /// Counts an operation served by the secondary because the primary failed
+    fn failover(&self) {
+        self.health.failovers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Combines the results of the same write applied to both backends
+    ///
+    /// Succeeds if either backend succeeded; otherwise returns the primary's error.
+    fn either<T>(primary: Result<T, Error>, secondary: Result<T, Error>) -> Result<T, Error> {
+        match (primary, secondary) {
+            (Ok(value), _) | (Err(_), Ok(value)) => Ok(value),
+            (Err(err), Err(_)) => Err(err),
+        }
+    }
+    /// Combines the results of the same removal applied to both backends
+    ///
+    /// Fails with the primary's error if the primary could not be reached, and with
+    /// the secondary's error if the secondary could not be reached while it may hold
+    /// sessions, i.e. unless the policy is `PrimaryOnly`. Otherwise behaves like
+    /// `either`.
+    fn removed<T>(
+        &self,
+        primary: Result<T, Error>,
+        secondary: Result<T, Error>,
+    ) -> Result<T, Error> {
+        match (primary, secondary) {
+            (Err(err), _) if err.is_backend_failure() => Err(err),
+            (_, Err(err))
+                if err.is_backend_failure() && self.policy != WritePolicy::PrimaryOnly =>
+            {
+                Err(err)
+            }
+            (primary, secondary) => Self::either(primary, secondary),
+        }
+    }
+}
+
+#[async_trait]
+impl<A, B> SessionStore for FailoverSessionStore<A, B>
+where
+    A: SessionStore,
+    B: SessionStore,
+{
+    /// Retrieves a session from the primary, falling back to the secondary
+    ///
+    /// The secondary is consulted when the primary fails, and also when the primary
+    /// does not know the session unless the policy is `PrimaryOnly`, since the
+    /// session may have been written only to the secondary during an earlier outage.
+    /// If the secondary cannot be reached then, its error is returned rather than
+    /// reporting the session as missing, which would log the user out.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to look up
+    ///
+    /// # Returns
+    /// Ok(Some(SessionInner)) if found, Ok(None) if neither backend has the session,
+    /// Err(Error) otherwise
+    async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error> {
+        match self.track_primary(self.primary.get(key).await) {
+            Ok(Some(value)) => Ok(Some(value)),
+            Ok(None) if self.policy != WritePolicy::PrimaryOnly => {
+                match self.track_secondary(self.secondary.get(key).await) {
+                    Err(err) if err.is_backend_failure() => Err(err),
+                    result => Ok(result.ok().flatten()),
+                }
+            }
+            Ok(None) => Ok(None),
+            Err(err) if err.is_backend_failure() => {
+                self.failover();
+                self.track_secondary(self.secondary.get(key).await)
+            }
+            Err(err) if self.policy != WritePolicy::PrimaryOnly => {
+                match self.track_secondary(self.secondary.get(key).await) {
+                    Ok(Some(value)) => Ok(Some(value)),
+                    _ => Err(err),
+                }
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Stores a session according to the write policy
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    ///
+    /// # Returns
+    /// Ok(()) if the write policy was satisfied, Err(Error) otherwise
+    async fn set(&self, key: &str, value: SessionInner) -> Result<(), Error> {
+        match self.policy {
+            WritePolicy::Both => {
+                let primary = self.track_primary(self.primary.set(key, value.clone()).await);
+                let secondary = self.track_secondary(self.secondary.set(key, value).await);
+                Self::either(primary, secondary)
+            }
+            WritePolicy::PrimaryOnly => self.track_primary(self.primary.set(key, value).await),
+            WritePolicy::SecondaryOnFailure => {
+                match self.track_primary(self.primary.set(key, value.clone()).await) {
+                    Err(err) if err.is_backend_failure() => {
+                        self.failover();
+                        self.track_secondary(self.secondary.set(key, value).await)
+                    }
+                    result => result,
+                }
+            }
+        }
+    }
+
+    /// Saves a session with its expiry according to the write policy
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    /// * `ttl` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if the write policy was satisfied, Err(Error) otherwise
+    async fn save(&self, key: &str, value: SessionInner, ttl: Duration) -> Result<(), Error> {
+        match self.policy {
+            WritePolicy::Both => {
+                let primary = self.track_primary(self.primary.save(key, value.clone(), ttl).await);
+                let secondary = self.track_secondary(self.secondary.save(key, value, ttl).await);
+                Self::either(primary, secondary)
+            }
+            WritePolicy::PrimaryOnly => {
+                self.track_primary(self.primary.save(key, value, ttl).await)
+            }
+            WritePolicy::SecondaryOnFailure => {
+                match self.track_primary(self.primary.save(key, value.clone(), ttl).await) {
+                    Err(err) if err.is_backend_failure() => {
+                        self.failover();
+                        self.track_secondary(self.secondary.save(key, value, ttl).await)
+                    }
+                    result => result,
+                }
+            }
+        }
+    }
+
+    /// Removes a session from both backends
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to remove
+    ///
+    /// # Returns
+    /// Ok(()) if the session is gone from every backend that may hold it, Err(Error)
+    /// if one of them could not be reached
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        let primary = self.track_primary(self.primary.remove(key).await);
+        let secondary = self.track_secondary(self.secondary.remove(key).await);
+        self.removed(primary, secondary)
+    }
+
+    /// Sets expiration time for a session according to the write policy
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to update
+    /// * `expire_time` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if the write policy was satisfied, Err(Error) otherwise
+    async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), Error> {
+        match self.policy {
+            WritePolicy::Both => {
+                let primary = self.track_primary(self.primary.expire(key, expire_time).await);
+                let secondary = self.track_secondary(self.secondary.expire(key, expire_time).await);
+                Self::either(primary, secondary)
+            }
+            WritePolicy::PrimaryOnly => {
+                self.track_primary(self.primary.expire(key, expire_time).await)
+            }
+            WritePolicy::SecondaryOnFailure => {
+                match self.track_primary(self.primary.expire(key, expire_time).await) {
+                    Err(err) if err.is_backend_failure() => {
+                        self.failover();
+                        self.track_secondary(self.secondary.expire(key, expire_time).await)
+                    }
+                    result => result,
+                }
+            }
+        }
+    }
+
+    /// Removes all sessions from both backends
+    ///
+    /// Both backends are always cleared, so an error reporting one backend as
+    /// unreachable may be returned after the other one has already been cleared.
+    ///
+    /// # Returns
+    /// Ok(count) with the number of sessions removed from the primary, or from the
+    /// secondary if the primary does not support clearing; Err(Error) if a backend
+    /// that may hold sessions could not be reached
+    async fn clear(&self) -> Result<usize, Error> {
+        let primary = self.track_primary(self.primary.clear().await);
+        let secondary = self.track_secondary(self.secondary.clear().await);
+        self.removed(primary, secondary)
+    }
+
+    /// Counts the sessions of the primary, falling back to the secondary if it fails
+    ///
+    /// # Returns
+    /// Ok(count) with the number of sessions, Err(Error) otherwise
+    async fn count(&self) -> Result<usize, Error> {
+        match self.track_primary(self.primary.count().await) {
+            Err(err) if err.is_backend_failure() => {
+                self.failover();
+                self.track_secondary(self.secondary.count().await)
+            }
+            result => result,
+        }
+    }
+
+    /// Lists one page of session IDs from the primary, falling back to the secondary
+    ///
+    /// A cursor is only meaningful to the backend that issued it; if the listing
+    /// switches backends, start over from the first page.
+    ///
+    /// # Arguments
+    /// * `cursor` - Cursor returned by the previous call, None for the first page
+    /// * `limit` - Approximate number of IDs to return
+    ///
+    /// # Returns
+    /// Ok(IdPage) with the IDs and the next cursor, Err(Error) otherwise
+    async fn ids(&self, cursor: Option<&str>, limit: usize) -> Result<IdPage, Error> {
+        match self.track_primary(self.primary.ids(cursor, limit).await) {
+            Err(err) if err.is_backend_failure() => {
+                self.failover();
+                self.track_secondary(self.secondary.ids(cursor, limit).await)
+            }
+            result => result,
+        }
+    }
+
+    /// Returns the remaining lifetime of a session from the primary, falling back to
+    /// the secondary
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to look up
+    ///
+    /// # Returns
+    /// Ok(Some(duration)) if the session expires, Ok(None) if it never expires,
+    /// Err(Error::NotFound) if neither backend has it
+    async fn ttl(&self, key: &str) -> Result<Option<Duration>, Error> {
+        match self.track_primary(self.primary.ttl(key).await) {
+            Err(err) if err.is_backend_failure() => {
+                self.failover();
+                self.track_secondary(self.secondary.ttl(key).await)
+            }
+            Err(Error::NotFound) if self.policy != WritePolicy::PrimaryOnly => {
+                self.track_secondary(self.secondary.ttl(key).await)
+            }
+            result => result,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "memory"))]
+mod tests {
+    use super::*;
+    use crate::memory::MemorySessionStorage;
+
+    /// Memory store failing every operation with a backend error while it is down
+    #[derive(Clone, Default)]
+    struct TestStore {
+        inner: MemorySessionStorage,
+        down: Arc<AtomicBool>,
+    }
+
+    impl TestStore {
+        fn set_down(&self, down: bool) {
+            self.down.store(down, Ordering::SeqCst);
+        }
+        fn check(&self) -> Result<(), Error> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(Error::backend("backend down"));
+            }
+            Ok(())
+        }
+        async fn user(&self, key: &str) -> Option<String> {
+            self.inner
+                .get(key)
+                .await
+                .unwrap()
+                .and_then(|inner| inner.get::<String>("user"))
+        }
+    }
+
+    #[async_trait]
+    impl SessionStore for TestStore {
+        async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error> {
+            self.check()?;
+            self.inner.get(key).await
+        }
+        async fn set(&self, key: &str, value: SessionInner) -> Result<(), Error> {
+            self.check()?;
+            self.inner.set(key, value).await
+        }
+        async fn remove(&self, key: &str) -> Result<(), Error> {
+            self.check()?;
+            self.inner.remove(key).await
+        }
+        async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), Error> {
+            self.check()?;
+            self.inner.expire(key, expire_time).await
+        }
+        async fn clear(&self) -> Result<usize, Error> {
+            self.check()?;
+            self.inner.clear().await
+        }
+    }
+
+    fn failover(policy: WritePolicy) -> FailoverSessionStore<TestStore, TestStore> {
+        FailoverSessionStore::new(TestStore::default(), TestStore::default(), policy)
+    }
+
+    fn session(user: &str) -> SessionInner {
+        let mut inner = SessionInner::new("a".to_string());
+        inner.set("user", user).unwrap();
+        inner
+    }
+
+    async fn user(store: &FailoverSessionStore<TestStore, TestStore>) -> Option<String> {
+        store
+            .get("a")
+            .await
+            .unwrap()
+            .and_then(|inner| inner.get::<String>("user"))
+    }
+
+    #[tokio::test]
+    async fn both_writes_to_each_backend_and_survives_one_failing() {
+        let store = failover(WritePolicy::Both);
+        store.set("a", session("alice")).await.unwrap();
+        assert_eq!(store.primary().user("a").await.as_deref(), Some("alice"));
+        assert_eq!(store.secondary().user("a").await.as_deref(), Some("alice"));
+
+        store.primary().set_down(true);
+        store
+            .save("a", session("bob"), Duration::minutes(5))
+            .await
+            .unwrap();
+        assert_eq!(user(&store).await.as_deref(), Some("bob"));
+        store.secondary().set_down(true);
+        assert!(store.set("a", session("carol")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn primary_only_never_writes_the_secondary() {
+        let store = failover(WritePolicy::PrimaryOnly);
+        store.set("a", session("alice")).await.unwrap();
+        assert_eq!(store.secondary().user("a").await, None);
+        store.primary().set_down(true);
+        assert!(store.set("a", session("bob")).await.is_err());
+        assert_eq!(store.secondary().user("a").await, None);
+    }
+
+    #[tokio::test]
+    async fn secondary_on_failure_writes_the_secondary_during_an_outage() {
+        let store = failover(WritePolicy::SecondaryOnFailure);
+        store.set("a", session("alice")).await.unwrap();
+        assert_eq!(store.secondary().user("a").await, None);
+        store.primary().set_down(true);
+        store.set("a", session("bob")).await.unwrap();
+        assert_eq!(store.secondary().user("a").await.as_deref(), Some("bob"));
+        assert_eq!(store.health().failovers, 1);
+    }
+
+    #[tokio::test]
+    async fn reads_fall_back_to_the_secondary() {
+        let store = failover(WritePolicy::Both);
+        store
+            .secondary()
+            .inner
+            .set("a", session("alice"))
+            .await
+            .unwrap();
+        // Written only to the secondary during an earlier outage
+        assert_eq!(user(&store).await.as_deref(), Some("alice"));
+        store.primary().set_down(true);
+        assert_eq!(user(&store).await.as_deref(), Some("alice"));
+
+        let store = failover(WritePolicy::PrimaryOnly);
+        store
+            .secondary()
+            .inner
+            .set("a", session("alice"))
+            .await
+            .unwrap();
+        assert_eq!(user(&store).await, None);
+        store.primary().set_down(true);
+        assert_eq!(user(&store).await.as_deref(), Some("alice"));
+
+        // The primary does not know the session and the secondary that may hold it is down
+        let store = failover(WritePolicy::Both);
+        store.secondary().set_down(true);
+        assert!(
+            store
+                .get("a")
+                .await
+                .is_err_and(|err| err.is_backend_failure())
+        );
+    }
+
+    #[tokio::test]
+    async fn health_counts_failures_and_failovers() {
+        let store = failover(WritePolicy::Both);
+        store.primary().set_down(true);
+        store.get("a").await.unwrap();
+        store.set("a", session("alice")).await.unwrap();
+        let health = store.health();
+        assert!(!health.primary_healthy);
+        assert!(health.secondary_healthy);
+        assert_eq!(health.primary_failures, 2);
+        assert_eq!(health.secondary_failures, 0);
+        assert_eq!(health.failovers, 1);
+
+        store.primary().set_down(false);
+        store.secondary().set_down(true);
+        store.set("a", session("bob")).await.unwrap();
+        let health = store.health();
+        assert!(health.primary_healthy);
+        assert!(!health.secondary_healthy);
+        assert_eq!(health.primary_failures, 2);
+        assert_eq!(health.secondary_failures, 1);
+    }
+
+    #[tokio::test]
+    async fn remove_fails_while_a_backend_holding_the_session_is_down() {
+        let store = failover(WritePolicy::Both);
+        store.set("a", session("alice")).await.unwrap();
+        store.primary().set_down(true);
+        assert!(store.remove("a").await.is_err());
+        store.primary().set_down(false);
+        // The caller was told the removal failed; the primary still has the session
+        assert_eq!(user(&store).await.as_deref(), Some("alice"));
+
+        store.secondary().set_down(true);
+        assert!(store.remove("a").await.is_err());
+        store.secondary().set_down(false);
+        store.remove("a").await.unwrap();
+        assert_eq!(user(&store).await, None);
+    }
+
+    #[tokio::test]
+    async fn primary_only_remove_ignores_the_secondary() {
+        let store = failover(WritePolicy::PrimaryOnly);
+        store.set("a", session("alice")).await.unwrap();
+        store.secondary().set_down(true);
+        store.remove("a").await.unwrap();
+        assert_eq!(store.primary().user("a").await, None);
+    }
+
+    #[tokio::test]
+    async fn clear_fails_while_a_backend_holding_sessions_is_down() {
+        let store = failover(WritePolicy::SecondaryOnFailure);
+        store.set("a", session("alice")).await.unwrap();
+        store.primary().set_down(true);
+        assert!(store.clear().await.is_err());
+        store.primary().set_down(false);
+        assert_eq!(user(&store).await.as_deref(), Some("alice"));
+        store.secondary().set_down(true);
+        assert!(store.clear().await.is_err());
+        // The error is reported after the primary has already been cleared
+        assert_eq!(store.primary().user("a").await, None);
+    }
+}
@@ -0,0 +1,442 @@
+//! Filesystem session storage implementation
+//!
+//! This module provides a session store keeping one JSON file per session under a
+//! configured directory. Files can be inspected with standard tools, and a directory
+//! on a shared volume can back several application instances. A session file looks
+//! like:
+//!
+//! ```json
+//! {"data":{"user_id":"42"},"expires_at":1767225600000}
+//! ```
+//!
+//! where `expires_at` is the expiry deadline in unix milliseconds, or null if the
+//! session never expires.
+
+use crate::storage::{SessionStore, now_millis, spawn_cleanup_task};
+use crate::{Error, SessionInner};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use time::Duration;
+use tokio::sync::{Mutex, MutexGuard};
+use tokio::task::JoinHandle;
+
+/// Extension of session files
+const EXTENSION: &str = "json";
+/// Extension of files being written before they are renamed into place
+const TMP_EXTENSION: &str = "tmp";
+/// Age after which an orphaned temporary file is removed by `cleanup`
+const TMP_MAX_AGE: std::time::Duration = std::time::Duration::from_secs(600);
+/// Counter making temporary file names unique within the process
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+/// Longest accepted session ID, the length of a `RandKey::Random(1024)` ID
+const MAX_ID_LEN: usize = 1366;
+/// Longest session ID used verbatim as a file name
+const MAX_NAME_LEN: usize = 200;
+/// File name prefix of sessions stored under the SHA-256 of their ID
+///
+/// The prefix is not a valid ID character, so hashed names cannot collide with
+/// IDs used verbatim.
+const HASHED_PREFIX: char = '~';
+/// Number of locks serializing the writes to session files
+const LOCK_STRIPES: usize = 64;
+
+/// On-disk representation of a session
+#[derive(Serialize, Deserialize)]
+struct FileEntry {
+    /// Session data, the same JSON object the Redis stores write
+    data: HashMap<String, String>,
+    /// Expiry deadline in unix milliseconds, if any
+    expires_at: Option<i64>,
+}
+
+impl FileEntry {
+    /// Returns true if the entry's deadline has passed
+    fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|at| at <= now_millis())
+    }
+}
+
+/// Returns true if `id` can be stored without naming a path outside the directory
+///
+/// Only non-empty IDs of at most 1366 ASCII letters, digits, `-` and `_` are
+/// accepted, which covers every built-in `RandKey` generator up to the 1024 bytes
+/// allowed by `SessionBuilder::rand_key`.
+fn valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Returns the file stem of session `id`
+///
+/// IDs longer than 200 characters are replaced by their SHA-256, keeping file names
+/// well within the limits of common filesystems.
+fn file_stem(id: &str) -> String {
+    if id.len() <= MAX_NAME_LEN {
+        id.to_string()
+    } else {
+        format!("{}{}", HASHED_PREFIX, sha256::digest(id))
+    }
+}
+
+/// Returns true if `stem` is a file stem produced by `file_stem`
+fn valid_stem(stem: &str) -> bool {
+    match stem.strip_prefix(HASHED_PREFIX) {
+        Some(hash) => hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()),
+        None => stem.len() <= MAX_NAME_LEN && valid_id(stem),
+    }
+}
+
+/// Filesystem-backed session storage implementation
+///
+/// Every write goes to a temporary file that is then renamed over the session file,
+/// so readers never observe a partially written session. Session IDs are validated
+/// before touching the filesystem; a crafted cookie cannot name a path outside the
+/// directory. Expiry is enforced on every read; expired files are physically deleted
+/// by `cleanup`, which can be scheduled with `spawn_cleanup`.
+///
+/// Writes, removals and expiry updates of the same session are serialized, so an
+/// `expire` rewriting a file can neither resurrect a session removed meanwhile nor
+/// overwrite a newer `save`. The locks are shared by clones of the storage; separate
+/// instances or processes using the same directory do not coordinate.
+#[derive(Clone)]
+pub struct FileSessionStorage {
+    /// Directory holding the session files
+    pub dir: PathBuf,
+    /// Locks guarding read-modify-write cycles, picked by hashing the file path
+    locks: Arc<[Mutex<()>]>,
+}
+
+impl FileSessionStorage {
+    /// Creates a new FileSessionStorage storing sessions under `dir`
+    ///
+    /// The directory is created if it does not exist yet.
+    ///
+    /// # Arguments
+    /// * `dir` - Directory dedicated to session files
+    ///
+    /// # Returns
+    /// Ok(FileSessionStorage) if the directory is usable, Err(Error) otherwise
+    pub fn new(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        Ok(FileSessionStorage {
+            dir: dir.as_ref().to_path_buf(),
+            locks: (0..LOCK_STRIPES).map(|_| Mutex::new(())).collect(),
+        })
+    }
+
+    /// Locks the session file at `path` against concurrent modifications
+    async fn lock(&self, path: &Path) -> MutexGuard<'_, ()> {
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+        self.locks[hasher.finish() as usize % self.locks.len()]
+            .lock()
+            .await
+    }
+
+    /// Returns the path of the file holding session `id`
+    ///
+    /// # Returns
+    /// Ok(path) for a valid ID, Err(Error::InvalidId) otherwise
+    fn path(&self, id: &str) -> Result<PathBuf, Error> {
+        if !valid_id(id) {
+            return Err(Error::InvalidId(id.to_string()));
+        }
+        Ok(self.dir.join(format!("{}.{}", file_stem(id), EXTENSION)))
+    }
+
+    /// Reads a session file, returning None if it does not exist
+    async fn read(&self, path: &Path) -> Result<Option<FileEntry>, Error> {
+        match tokio::fs::read(path).await {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Atomically replaces a session file
+    async fn write(&self, path: &Path, entry: &FileEntry) -> Result<(), Error> {
+        let tmp = path.with_extension(format!(
+            "{}.{}.{}",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed),
+            TMP_EXTENSION
+        ));
+        tokio::fs::write(&tmp, serde_json::to_vec(entry)?).await?;
+        if let Err(err) = tokio::fs::rename(&tmp, path).await {
+            tokio::fs::remove_file(&tmp).await.ok();
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    /// Deletes a file, treating a missing file as success
+    ///
+    /// # Returns
+    /// Ok(true) if a file was deleted, Ok(false) if it did not exist
+    async fn delete(path: &Path) -> Result<bool, Error> {
+        match tokio::fs::remove_file(path).await {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Lists the session files in the directory
+    async fn sessions(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut sessions = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == EXTENSION)
+                && path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .is_some_and(valid_stem)
+            {
+                sessions.push(path);
+            }
+        }
+        Ok(sessions)
+    }
+
+    /// Deletes expired session files and orphaned temporary files
+    ///
+    /// Unreadable or corrupt session files are left untouched.
+    ///
+    /// # Returns
+    /// Ok(count) with the number of sessions deleted, Err(Error) if the directory cannot be read
+    pub async fn cleanup(&self) -> Result<u64, Error> {
+        let mut removed = 0;
+        for path in self.sessions().await? {
+            let _guard = self.lock(&path).await;
+            if let Ok(Some(entry)) = self.read(&path).await
+                && entry.is_expired()
+                && FileSessionStorage::delete(&path).await?
+            {
+                removed += 1;
+            }
+        }
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == TMP_EXTENSION)
+                && let Ok(modified) = entry.metadata().await.and_then(|meta| meta.modified())
+                && modified.elapsed().is_ok_and(|age| age > TMP_MAX_AGE)
+            {
+                FileSessionStorage::delete(&path).await?;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Deletes expired session files and stale temporary files every `interval` from a background task
+    ///
+    /// Abort the returned handle to stop it.
+    ///
+    /// # Panics
+    /// Panics if the interval is not positive or if called outside a tokio runtime
+    pub fn spawn_cleanup(&self, interval: Duration) -> JoinHandle<()> {
+        let storage = self.clone();
+        spawn_cleanup_task(interval, move || {
+            let storage = storage.clone();
+            async move { storage.cleanup().await }
+        })
+    }
+}
+
+#[async_trait]
+impl SessionStore for FileSessionStorage {
+    /// Retrieves a session from its file
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to look up
+    ///
+    /// # Returns
+    /// Ok(Some(SessionInner)) if present and not expired, Ok(None) if missing, expired
+    /// or not a valid ID, Err(Error) if the file cannot be read
+    async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error> {
+        if !valid_id(key) {
+            return Ok(None);
+        }
+        Ok(self
+            .read(&self.path(key)?)
+            .await?
+            .filter(|entry| !entry.is_expired())
+            .map(|entry| SessionInner::loaded(key.to_string(), entry.data)))
+    }
+
+    /// Stores a session in its file
+    ///
+    /// Overwriting an existing session clears its expiry, matching the behaviour
+    /// of a plain Redis `SET`.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if the ID is invalid or the write fails
+    async fn set(&self, key: &str, value: SessionInner) -> Result<(), Error> {
+        let path = self.path(key)?;
+        let entry = FileEntry {
+            data: value.data,
+            expires_at: None,
+        };
+        let _guard = self.lock(&path).await;
+        self.write(&path, &entry).await
+    }
+
+    /// Stores a session in its file together with its expiry
+    ///
+    /// A non-positive duration removes the session.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    /// * `ttl` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if the ID is invalid or the write fails
+    async fn save(&self, key: &str, value: SessionInner, ttl: Duration) -> Result<(), Error> {
+        let path = self.path(key)?;
+        let _guard = self.lock(&path).await;
+        if !ttl.is_positive() {
+            FileSessionStorage::delete(&path).await?;
+            return Ok(());
+        }
+        let entry = FileEntry {
+            data: value.data,
+            expires_at: Some(now_millis().saturating_add(ttl.whole_milliseconds() as i64)),
+        };
+        self.write(&path, &entry).await
+    }
+
+    /// Removes a session file
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to remove
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if the ID is invalid or removal fails
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        let path = self.path(key)?;
+        let _guard = self.lock(&path).await;
+        FileSessionStorage::delete(&path).await?;
+        Ok(())
+    }
+
+    /// Sets expiration time for a session
+    ///
+    /// A non-positive duration deletes the session immediately.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to update
+    /// * `expire_time` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if the ID is invalid or the update fails
+    async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), Error> {
+        let path = self.path(key)?;
+        let _guard = self.lock(&path).await;
+        if !expire_time.is_positive() {
+            FileSessionStorage::delete(&path).await?;
+            return Ok(());
+        }
+        if let Some(mut entry) = self.read(&path).await? {
+            entry.expires_at =
+                Some(now_millis().saturating_add(expire_time.whole_milliseconds() as i64));
+            self.write(&path, &entry).await?;
+        }
+        Ok(())
+    }
+
+    /// Removes all session files from the directory
+    ///
+    /// Files that are not session files are left untouched.
+    ///
+    /// # Returns
+    /// Ok(count) with the number of sessions removed, Err(Error) if clear operation fails
+    async fn clear(&self) -> Result<usize, Error> {
+        let mut removed = 0;
+        for path in self.sessions().await? {
+            let _guard = self.lock(&path).await;
+            if FileSessionStorage::delete(&path).await? {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_id_rejects_path_traversal() {
+        for id in [
+            "../x", "a/b", "a\\b", "..", ".", "", "a.json", "a b", "a\0b",
+        ] {
+            assert!(!valid_id(id), "{id:?} must be rejected");
+        }
+        assert!(valid_id("0190a3f2-7c3e-7cc1-8f7a-2b3c4d5e6f70"));
+        assert!(valid_id("Zm9v_YmFy-"));
+    }
+
+    #[test]
+    fn long_ids_are_hashed_into_short_file_names() {
+        let long = "a".repeat(1366);
+        assert!(valid_id(&long));
+        assert!(!valid_id(&"a".repeat(1367)));
+        let stem = file_stem(&long);
+        assert!(stem.starts_with(HASHED_PREFIX) && stem.len() == 65);
+        assert!(valid_stem(&stem));
+        assert_eq!(file_stem("abc"), "abc");
+        assert!(!valid_stem(&"a".repeat(201)));
+    }
+
+    #[tokio::test]
+    async fn paths_stay_inside_the_directory() {
+        let dir = std::env::temp_dir().join(format!("rsession-file-{}", std::process::id()));
+        let store = FileSessionStorage::new(&dir).unwrap();
+        assert!(matches!(store.path("../x"), Err(Error::InvalidId(_))));
+        assert!(store.get("../x").await.unwrap().is_none());
+        let long = "b".repeat(1000);
+        store
+            .save(&long, SessionInner::new(long.clone()), Duration::hours(1))
+            .await
+            .unwrap();
+        assert!(store.path(&long).unwrap().starts_with(&dir));
+        assert!(store.get(&long).await.unwrap().is_some());
+        assert_eq!(store.clear().await.unwrap(), 1);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn remove_during_expire_is_not_undone() {
+        let dir = std::env::temp_dir().join(format!("rsession-file-race-{}", std::process::id()));
+        let store = FileSessionStorage::new(&dir).unwrap();
+        for i in 0..100 {
+            let id = format!("race{i}");
+            store
+                .save(&id, SessionInner::new(id.clone()), Duration::hours(1))
+                .await
+                .unwrap();
+            let (expired, removed) =
+                tokio::join!(store.expire(&id, Duration::hours(2)), store.remove(&id));
+            expired.unwrap();
+            removed.unwrap();
+            assert!(store.get(&id).await.unwrap().is_none(), "{id} came back");
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,266 @@
+//! Memcached session storage implementation
+//!
+//! This module provides a session store backed by memcached. Session expiry maps to
+//! memcached's native exptime, so no cleanup task is needed.
+//!
+//! Memcached cannot enumerate or delete keys by pattern, so sessions are namespaced
+//! by a version number kept under `{prefix}ns`. Session keys have the form
+//! `{prefix}{version}:{id}`; `clear` bumps the version, which makes every existing
+//! session unreachable until memcached evicts it, without flushing the server.
+//! Refreshing expiry uses the meta protocol, which requires memcached 1.6 or later.
+//!
+//! The version key has no expiry but, like any item, can be evicted when the server
+//! runs out of memory. It is read on every request, which keeps it at the hot end
+//! of memcached's LRU, but if it is evicted anyway a new version starts and every
+//! existing session of the store is lost at once, as if `clear` had been called.
+//! Give the server enough memory for sessions not to be evicted in the first place.
+
+use crate::storage::SessionStore;
+use crate::{Error, SessionInner};
+use async_memcached::{AsciiProtocol, MetaProtocol};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use time::{Duration, OffsetDateTime};
+
+/// Longest key memcached accepts, in bytes
+const MAX_KEY_LEN: usize = 250;
+
+/// Largest relative exptime memcached accepts; longer ones must be absolute timestamps
+const MAX_RELATIVE_EXPTIME: i64 = 60 * 60 * 24 * 30;
+
+/// Converts a session TTL into a memcached exptime
+///
+/// Durations are rounded up to whole seconds. Durations longer than 30 days are
+/// sent as an absolute unix timestamp, as required by the memcached protocol.
+fn exptime(expire_time: Duration) -> i64 {
+    let seconds = expire_time.whole_seconds() + i64::from(expire_time.subsec_nanoseconds() > 0);
+    if seconds > MAX_RELATIVE_EXPTIME {
+        OffsetDateTime::now_utc().unix_timestamp() + seconds
+    } else {
+        seconds
+    }
+}
+
+/// Returns true if `id` can be sent in a memcached text protocol command
+fn valid_id(id: &str) -> bool {
+    !id.is_empty() && !id.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+/// Memcached-backed session storage implementation
+///
+/// Uses a connection pool to manage memcached connections and a key prefix for
+/// namespacing sessions on shared servers.
+#[derive(Clone)]
+pub struct MemcachedSessionStorage {
+    pub pool: deadpool_memcached::Pool,
+    pub prefix: String,
+}
+
+impl MemcachedSessionStorage {
+    /// Creates a new MemcachedSessionStorage instance using the `rsession:` prefix
+    ///
+    /// # Arguments
+    /// * `pool` - A deadpool-memcached connection pool
+    pub fn new(pool: deadpool_memcached::Pool) -> Self {
+        MemcachedSessionStorage {
+            pool,
+            prefix: "rsession:".to_string(),
+        }
+    }
+    /// Sets the key prefix for memcached storage
+    ///
+    /// Stores sharing a server must use distinct prefixes so that `clear` on one of
+    /// them leaves the others intact.
+    ///
+    /// # Arguments
+    /// * `prefix` - String to prepend to all memcached keys
+    pub fn set_prefix(&mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self.clone()
+    }
+
+    /// Gets a memcached connection from the pool
+    async fn get_conn(&self) -> Result<deadpool_memcached::Object, Error> {
+        self.pool.get().await.map_err(Error::pool)
+    }
+
+    /// Returns the key holding the namespace version
+    fn version_key(&self) -> String {
+        format!("{}ns", self.prefix)
+    }
+
+    /// Returns the current namespace version, initializing it if missing
+    ///
+    /// A missing version (never set, or evicted) is initialized to the current time
+    /// in milliseconds, so that it never moves back to a value used before.
+    async fn version(&self, conn: &mut async_memcached::Client) -> Result<u64, Error> {
+        let key = self.version_key();
+        loop {
+            if let Some(value) = conn.get(&key).await.map_err(Error::backend)? {
+                return value
+                    .data
+                    .and_then(|data| String::from_utf8(data).ok())
+                    .and_then(|data| data.trim().parse().ok())
+                    .ok_or_else(|| Error::backend("invalid namespace version"));
+            }
+            let initial = (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as u64;
+            match conn.add(&key, initial, None, None).await {
+                Ok(()) => return Ok(initial),
+                // Another client initialized it first; read its value
+                Err(async_memcached::Error::Protocol(async_memcached::Status::NotStored)) => {}
+                Err(err) => return Err(Error::backend(err)),
+            }
+        }
+    }
+
+    /// Returns the memcached key of session `id` in the current namespace
+    ///
+    /// # Returns
+    /// Ok(key), or Err(Error::InvalidId) if the ID contains whitespace or
+    /// control characters, which the memcached text protocol cannot carry, or if
+    /// the resulting key is longer than the 250 bytes memcached accepts
+    async fn key(&self, conn: &mut async_memcached::Client, id: &str) -> Result<String, Error> {
+        if !valid_id(id) {
+            return Err(Error::InvalidId(id.to_string()));
+        }
+        let version = self.version(conn).await?;
+        let key = format!("{}{}:{}", self.prefix, version, id);
+        if key.len() > MAX_KEY_LEN {
+            return Err(Error::InvalidId(id.to_string()));
+        }
+        Ok(key)
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemcachedSessionStorage {
+    /// Retrieves a session from memcached by key
+    ///
+    /// # Arguments
+    /// * `key` - Session ID to retrieve
+    ///
+    /// # Returns
+    /// Ok(Some(SessionInner)) if found, Ok(None) if missing or not a valid ID (including
+    /// IDs too long for a memcached key), or an error if retrieval fails
+    async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error> {
+        if !valid_id(key) {
+            return Ok(None);
+        }
+        let mut conn = self.get_conn().await?;
+        let item = match self.key(&mut conn, key).await {
+            Ok(item) => item,
+            Err(Error::InvalidId(_)) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let data = conn
+            .get(&item)
+            .await
+            .map_err(Error::backend)?
+            .and_then(|value| value.data);
+        let Some(data) = data else {
+            return Ok(None);
+        };
+        let map = serde_json::from_slice::<HashMap<String, String>>(&data)?;
+        Ok(Some(SessionInner::loaded(key.to_string(), map)))
+    }
+
+    /// Stores a session in memcached
+    ///
+    /// The item is stored without exptime; `expire` sets it afterwards.
+    ///
+    /// # Arguments
+    /// * `key` - Session ID to store
+    /// * `value` - SessionInner data to persist
+    ///
+    /// # Returns
+    /// Ok(()) on success, or an error if serialization or storage fails
+    async fn set(&self, key: &str, value: SessionInner) -> Result<(), Error> {
+        let data = serde_json::to_string(&value.data)?;
+        let mut conn = self.get_conn().await?;
+        let item = self.key(&mut conn, key).await?;
+        conn.set(&item, &data, None, None)
+            .await
+            .map_err(Error::backend)
+    }
+
+    /// Stores a session in memcached with its exptime in a single `set`
+    ///
+    /// A non-positive duration removes the session.
+    ///
+    /// # Arguments
+    /// * `key` - Session ID to store
+    /// * `value` - SessionInner data to persist
+    /// * `ttl` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) on success, or an error if serialization or storage fails
+    async fn save(&self, key: &str, value: SessionInner, ttl: Duration) -> Result<(), Error> {
+        if !ttl.is_positive() {
+            return self.remove(key).await;
+        }
+        let data = serde_json::to_string(&value.data)?;
+        let mut conn = self.get_conn().await?;
+        let item = self.key(&mut conn, key).await?;
+        conn.set(&item, &data, Some(exptime(ttl)), None)
+            .await
+            .map_err(Error::backend)
+    }
+
+    /// Removes a session from memcached
+    ///
+    /// # Arguments
+    /// * `key` - Session ID to remove
+    ///
+    /// # Returns
+    /// Ok(()) on success, whether or not the session existed
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        let mut conn = self.get_conn().await?;
+        let item = self.key(&mut conn, key).await?;
+        match conn.delete(&item).await {
+            Ok(()) | Err(async_memcached::Error::Protocol(async_memcached::Status::NotFound)) => {
+                Ok(())
+            }
+            Err(err) => Err(Error::backend(err)),
+        }
+    }
+
+    /// Sets the exptime of a session in memcached
+    ///
+    /// A non-positive duration deletes the session immediately.
+    ///
+    /// # Arguments
+    /// * `key` - Session ID to set expiration for
+    /// * `expire_time` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) on success, or an error if the operation fails
+    async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), Error> {
+        if !expire_time.is_positive() {
+            return self.remove(key).await;
+        }
+        let mut conn = self.get_conn().await?;
+        let item = self.key(&mut conn, key).await?;
+        let ttl = format!("T{}", exptime(expire_time));
+        conn.meta_get(&item, false, None, Some(&[ttl.as_str()]))
+            .await
+            .map_err(Error::backend)?;
+        Ok(())
+    }
+
+    /// Invalidates all sessions of this storage by bumping the namespace version
+    ///
+    /// Only keys under this storage's prefix are affected; the server is not flushed.
+    /// Memcached cannot count the invalidated items, so the returned count is always 0
+    /// even when sessions were removed.
+    ///
+    /// # Returns
+    /// Ok(0) on success, or an error if the version could not be bumped
+    async fn clear(&self) -> Result<usize, Error> {
+        let mut conn = self.get_conn().await?;
+        self.version(&mut conn).await?;
+        conn.increment(self.version_key(), 1)
+            .await
+            .map_err(Error::backend)?;
+        Ok(0)
+    }
+}
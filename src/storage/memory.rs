@@ -0,0 +1,256 @@
+//! In-memory session storage implementation
+//!
+//! This module provides a process-local session store backed by a concurrent map.
+//! It needs no external infrastructure, which makes it a good fit for tests, local
+//! development and single-node services. Sessions do not survive a restart.
+
+use crate::storage::SessionStore;
+use crate::{Error, SessionInner};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+use time::{Duration, OffsetDateTime};
+use tokio::sync::RwLock;
+
+/// A single stored session together with its expiry deadline
+#[derive(Debug, Clone)]
+struct MemoryEntry {
+    /// Serialized session data
+    data: HashMap<String, String>,
+    /// Point in time after which the entry is considered gone, if any
+    expires_at: Option<OffsetDateTime>,
+}
+
+impl MemoryEntry {
+    /// Returns true if the entry's deadline has passed at `now`
+    fn is_expired(&self, now: OffsetDateTime) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+type MemoryMap = RwLock<HashMap<String, MemoryEntry>>;
+
+/// In-memory session storage backend
+///
+/// Cloning the storage is cheap and every clone shares the same underlying map,
+/// so a single instance can be handed to every actix worker, axum router or salvo
+/// service. Expired sessions are never returned by `get`, and a background tokio
+/// task periodically evicts them so memory does not grow without bound.
+#[derive(Clone)]
+pub struct MemorySessionStorage {
+    /// Shared session map keyed by session ID
+    map: Arc<MemoryMap>,
+}
+
+impl Default for MemorySessionStorage {
+    fn default() -> Self {
+        MemorySessionStorage::new()
+    }
+}
+
+impl MemorySessionStorage {
+    /// Creates a new MemorySessionStorage sweeping expired sessions every 60 seconds
+    ///
+    /// The sweeper is spawned on the current tokio runtime. When called outside of a
+    /// runtime no sweeper is started; expired sessions are then still hidden from
+    /// `get` but only reclaimed when they are overwritten or removed.
+    pub fn new() -> Self {
+        MemorySessionStorage::with_sweep_interval(Duration::seconds(60))
+    }
+    /// Creates a new MemorySessionStorage with a custom sweep interval
+    ///
+    /// # Arguments
+    /// * `interval` - How often the background task evicts expired sessions
+    ///
+    /// # Panics
+    /// Panics if the interval is not positive
+    pub fn with_sweep_interval(interval: Duration) -> Self {
+        assert!(interval.is_positive(), "interval must be positive");
+        let storage = MemorySessionStorage {
+            map: Arc::new(RwLock::new(HashMap::new())),
+        };
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(Self::sweep(Arc::downgrade(&storage.map), interval));
+        }
+        storage
+    }
+    /// Returns the number of sessions currently held, including expired ones
+    /// that have not been swept yet
+    pub async fn len(&self) -> usize {
+        self.map.read().await.len()
+    }
+    /// Returns true if no sessions are currently held
+    pub async fn is_empty(&self) -> bool {
+        self.map.read().await.is_empty()
+    }
+
+    /// Background loop evicting expired sessions
+    ///
+    /// Holds only a weak reference to the map and stops once every
+    /// MemorySessionStorage clone has been dropped.
+    async fn sweep(map: Weak<MemoryMap>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval.unsigned_abs());
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let Some(map) = map.upgrade() else {
+                break;
+            };
+            let now = OffsetDateTime::now_utc();
+            map.write().await.retain(|_, entry| !entry.is_expired(now));
+        }
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemorySessionStorage {
+    /// Retrieves a session from memory
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to look up
+    ///
+    /// # Returns
+    /// Ok(Some(SessionInner)) if present and not expired, Ok(None) otherwise
+    async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error> {
+        let map = self.map.read().await;
+        Ok(map
+            .get(key)
+            .filter(|entry| !entry.is_expired(OffsetDateTime::now_utc()))
+            .map(|entry| SessionInner::loaded(key.to_string(), entry.data.clone())))
+    }
+
+    /// Stores a session in memory
+    ///
+    /// Overwriting an existing session clears its expiry, matching the behaviour
+    /// of a plain Redis `SET`.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    ///
+    /// # Returns
+    /// Always Ok(())
+    async fn set(&self, key: &str, value: SessionInner) -> Result<(), Error> {
+        self.map.write().await.insert(
+            key.to_string(),
+            MemoryEntry {
+                data: value.data,
+                expires_at: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Stores a session in memory together with its expiry
+    ///
+    /// A non-positive duration removes the session.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    /// * `ttl` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Always Ok(())
+    async fn save(&self, key: &str, value: SessionInner, ttl: Duration) -> Result<(), Error> {
+        let mut map = self.map.write().await;
+        if ttl.is_positive() {
+            map.insert(
+                key.to_string(),
+                MemoryEntry {
+                    data: value.data,
+                    expires_at: Some(OffsetDateTime::now_utc() + ttl),
+                },
+            );
+        } else {
+            map.remove(key);
+        }
+        Ok(())
+    }
+
+    /// Removes a session from memory
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to remove
+    ///
+    /// # Returns
+    /// Always Ok(), whether or not the session existed
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        self.map.write().await.remove(key);
+        Ok(())
+    }
+
+    /// Sets expiration time for a session in memory
+    ///
+    /// A non-positive duration expires the session immediately.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to update
+    /// * `expire_time` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Always Ok(), whether or not the session existed
+    async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), Error> {
+        let mut map = self.map.write().await;
+        if expire_time.is_positive() {
+            if let Some(entry) = map.get_mut(key) {
+                entry.expires_at = Some(OffsetDateTime::now_utc() + expire_time);
+            }
+        } else {
+            map.remove(key);
+        }
+        Ok(())
+    }
+
+    /// Removes all sessions held by this storage
+    ///
+    /// Only affects this storage and its clones.
+    ///
+    /// # Returns
+    /// Ok(count) with the number of sessions removed, including expired ones not yet swept
+    async fn clear(&self) -> Result<usize, Error> {
+        let mut map = self.map.write().await;
+        let removed = map.len();
+        map.clear();
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: &str) -> SessionInner {
+        let mut inner = SessionInner::new(id.to_string());
+        inner.set("user", "alice").unwrap();
+        inner
+    }
+
+    #[tokio::test]
+    async fn expired_sessions_are_hidden_and_swept() {
+        let store = MemorySessionStorage::with_sweep_interval(Duration::milliseconds(20));
+        store
+            .save("short", session("short"), Duration::milliseconds(50))
+            .await
+            .unwrap();
+        store.set("kept", session("kept")).await.unwrap();
+        assert!(store.get("short").await.unwrap().is_some());
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+        assert!(store.get("short").await.unwrap().is_none());
+        assert!(store.get("kept").await.unwrap().is_some());
+        assert_eq!(store.len().await, 1);
+    }
+
+    #[tokio::test]
+    async fn expire_refreshes_and_non_positive_expire_removes() {
+        let store = MemorySessionStorage::new();
+        store.set("a", session("a")).await.unwrap();
+        store.expire("a", Duration::milliseconds(50)).await.unwrap();
+        store.expire("a", Duration::hours(1)).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let loaded = store.get("a").await.unwrap().unwrap();
+        assert_eq!(loaded.get::<String>("user").as_deref(), Some("alice"));
+        store.expire("a", Duration::ZERO).await.unwrap();
+        assert!(store.get("a").await.unwrap().is_none());
+    }
+}
@@ -2,10 +2,20 @@
 //!
 //! This module defines the common interface for session storage backends and
 //! provides Redis-based implementations through feature flags.
-use crate::SessionInner;
+use crate::{Error, SessionInner};
 use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
 use time::Duration;
 
+/// One page of session IDs returned by `SessionStore::ids`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdPage {
+    /// Session IDs in this page
+    pub ids: Vec<String>,
+    /// Cursor to pass to the next call, or None once every session has been listed
+    pub cursor: Option<String>,
+}
+
 /// Common interface for session storage backends
 ///
 /// This trait defines the required operations for persistent session storage
@@ -15,12 +25,16 @@ use time::Duration;
 pub trait SessionStore: Clone + Sync + Send + 'static {
     /// Retrieves a session from storage by key
     ///
+    /// A missing or expired session is not an error; errors are reserved for
+    /// backend failures and undecodable data, so callers can tell them apart.
+    ///
     /// # Arguments
     /// * `key` - Session identifier to look up
     ///
     /// # Returns
-    /// Ok(SessionInner) if found, Err(io::Error) if retrieval fails
-    async fn get(&self, key: &str) -> Result<SessionInner, std::io::Error>;
+    /// Ok(Some(SessionInner)) if found, Ok(None) if the store has no such session,
+    /// Err(Error) if retrieval fails
+    async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error>;
     /// Stores a session in storage with the given key
     ///
     /// # Arguments
@@ -28,16 +42,33 @@ pub trait SessionStore: Clone + Sync + Send + 'static {
     /// * `value` - SessionInner instance containing the session data
     ///
     /// # Returns
-    /// Ok(()) if successful, Err(io::Error) if storage fails
-    async fn set(&self, key: &str, value: SessionInner) -> Result<(), std::io::Error>;
+    /// Ok(()) if successful, Err(Error) if storage fails
+    async fn set(&self, key: &str, value: SessionInner) -> Result<(), Error>;
+    /// Stores a session together with its expiration
+    ///
+    /// Backends should override this with a single atomic write so a session can
+    /// never be observed without its expiry. The default implementation calls `set`
+    /// followed by `expire`. A non-positive duration removes the session.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session
+    /// * `value` - SessionInner instance containing the session data
+    /// * `ttl` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if storage fails
+    async fn save(&self, key: &str, value: SessionInner, ttl: Duration) -> Result<(), Error> {
+        self.set(key, value).await?;
+        self.expire(key, ttl).await
+    }
     /// Removes a specific session from storage
     ///
     /// # Arguments
     /// * `key` - Session identifier to remove
     ///
     /// # Returns
-    /// Ok(()) if successful, Err(io::Error) if removal fails
-    async fn remove(&self, key: &str) -> Result<(), std::io::Error>;
+    /// Ok(()) if successful, Err(Error) if removal fails
+    async fn remove(&self, key: &str) -> Result<(), Error>;
     /// Sets expiration duration for a session
     ///
     /// # Arguments
@@ -45,16 +76,84 @@ pub trait SessionStore: Clone + Sync + Send + 'static {
     /// * `expire_time` - Duration until the session expires
     ///
     /// # Returns
-    /// Ok(()) if successful, Err(io::Error) if expiration update fails
-    async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), std::io::Error>;
-    /// Removes all sessions from storage
+    /// Ok(()) if successful, Err(Error) if expiration update fails
+    async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), Error>;
+    /// Removes all sessions belonging to this storage
+    ///
+    /// Implementations must only delete the sessions they manage (for example the
+    /// keys under their prefix), never unrelated data sharing the same backend.
     ///
     /// # Warning
     /// This is a destructive operation that will delete all session data
     ///
     /// # Returns
-    /// Ok(()) if successful, Err(io::Error) if clear operation fails
-    async fn clear(&self) -> Result<(), std::io::Error>;
+    /// Ok(count) with the number of sessions removed, Err(Error) if clear operation fails.
+    /// Backends that invalidate sessions without being able to count them, such as
+    /// memcached, always report 0.
+    async fn clear(&self) -> Result<usize, Error>;
+    /// Counts the sessions belonging to this storage
+    ///
+    /// May have to walk every session, so it is meant for occasional use such as
+    /// an admin dashboard rather than for every request.
+    ///
+    /// # Returns
+    /// Ok(count) with the number of sessions, Err(Error::Unsupported) if the backend
+    /// cannot enumerate its sessions
+    async fn count(&self) -> Result<usize, Error> {
+        Err(Error::Unsupported("count"))
+    }
+    /// Lists one page of the session IDs belonging to this storage
+    ///
+    /// Start with no cursor and pass the returned cursor back until it is None.
+    /// `limit` is a hint: like a Redis `SCAN`, a page may hold fewer or a few more
+    /// IDs, and may even be empty before the listing is complete. Sessions created
+    /// or removed while listing may or may not be returned.
+    ///
+    /// # Arguments
+    /// * `cursor` - Cursor returned by the previous call, None for the first page
+    /// * `limit` - Approximate number of IDs to return
+    ///
+    /// # Returns
+    /// Ok(IdPage) with the IDs and the next cursor, Err(Error::Unsupported) if the
+    /// backend cannot enumerate its sessions
+    async fn ids(&self, cursor: Option<&str>, limit: usize) -> Result<IdPage, Error> {
+        let _ = (cursor, limit);
+        Err(Error::Unsupported("ids"))
+    }
+    /// Returns the remaining lifetime of a session
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to look up
+    ///
+    /// # Returns
+    /// Ok(Some(duration)) if the session expires, Ok(None) if it never expires,
+    /// Err(Error::NotFound) if it does not exist, Err(Error::Unsupported) if the
+    /// backend cannot report expiry times
+    async fn ttl(&self, key: &str) -> Result<Option<Duration>, Error> {
+        let _ = key;
+        Err(Error::Unsupported("ttl"))
+    }
+    /// Streams every session ID belonging to this storage
+    ///
+    /// Fetches pages of about `page_size` IDs with `ids` as the stream is consumed.
+    ///
+    /// # Arguments
+    /// * `page_size` - Approximate number of IDs fetched per round trip
+    fn stream_ids(&self, page_size: usize) -> BoxStream<'_, Result<String, Error>> {
+        stream::try_unfold(
+            Some(None),
+            move |cursor: Option<Option<String>>| async move {
+                let Some(cursor) = cursor else {
+                    return Ok::<_, Error>(None);
+                };
+                let page = self.ids(cursor.as_deref(), page_size).await?;
+                Ok(Some((page.ids, page.cursor.map(Some))))
+            },
+        )
+        .map_ok(|ids| stream::iter(ids.into_iter().map(Ok)))
+        .try_flatten()
+        .boxed()
+    }
 }
 
 /// Type-erased wrapper for different Redis connection pools
@@ -79,9 +178,83 @@ pub enum SessionStoreInner {
     RedisSentinel(deadpool_redis::sentinel::Pool),
 }
 
+/// Returns true if `name` is safe to interpolate into SQL as a table or schema name
+///
+/// Only non-empty ASCII identifiers made of letters, digits and underscores and not
+/// starting with a digit are accepted, so no quoting or escaping is ever required.
+#[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
+pub(crate) fn valid_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 63
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Returns the current time in unix milliseconds
+#[cfg(any(feature = "file", feature = "redb"))]
+pub(crate) fn now_millis() -> i64 {
+    (time::OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+/// Spawns a background task running a store's cleanup routine at a fixed interval
+///
+/// Failed runs are ignored and retried at the next tick. The task runs until the
+/// returned handle is aborted.
+///
+/// # Panics
+/// Panics if the interval is not positive or if called outside a tokio runtime
+#[cfg(any(
+    feature = "sqlite",
+    feature = "postgres",
+    feature = "mysql",
+    feature = "redb",
+    feature = "file"
+))]
+pub(crate) fn spawn_cleanup_task<F, Fut>(
+    interval: Duration,
+    mut cleanup: F,
+) -> tokio::task::JoinHandle<()>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<u64, Error>> + Send,
+{
+    assert!(interval.is_positive(), "interval must be positive");
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval.unsigned_abs());
+        loop {
+            ticker.tick().await;
+            cleanup().await.ok();
+        }
+    })
+}
+
+#[cfg(feature = "cached")]
+pub mod cached;
+#[cfg(feature = "failover")]
+pub mod failover;
+#[cfg(feature = "file")]
+pub mod file;
+#[cfg(feature = "memcached")]
+pub mod memcached;
+#[cfg(feature = "memory")]
+pub mod memory;
+#[cfg(feature = "mongodb")]
+pub mod mongodb;
+#[cfg(feature = "mysql")]
+pub mod mysql;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "redb")]
+pub mod redb;
 #[cfg(feature = "redis")]
 pub mod redis;
 #[cfg(feature = "redis-cluster")]
 pub mod redis_cluster;
 #[cfg(feature = "redis-sentinel")]
 pub mod redis_sentinel;
+#[cfg(feature = "sharded")]
+pub mod sharded;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(all(test, any(feature = "sqlite", feature = "redb")))]
+pub(crate) mod testing;
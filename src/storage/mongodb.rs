@@ -0,0 +1,228 @@
+//! MongoDB session storage implementation
+//!
+//! This module provides a session store keeping one document per session in a
+//! MongoDB collection, so session data stays queryable next to other documents:
+//!
+//! ```json
+//! { "_id": "<session id>", "data": { "user_id": "42" }, "expiresAt": ISODate("...") }
+//! ```
+//!
+//! `data` holds the same key/value pairs the Redis stores write, and `expiresAt` is
+//! null for sessions without expiry. A TTL index on `expiresAt` lets MongoDB delete
+//! expired sessions on its own.
+
+use crate::storage::SessionStore;
+use crate::{Error, SessionInner};
+use ::mongodb::bson::{Bson, DateTime, Document, doc};
+use ::mongodb::options::IndexOptions;
+use ::mongodb::{Collection, Database, IndexModel};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use time::{Duration, OffsetDateTime};
+
+/// Returns the MongoDB date `expire_time` from now
+fn deadline(expire_time: Duration) -> DateTime {
+    let at = OffsetDateTime::now_utc() + expire_time;
+    DateTime::from_millis((at.unix_timestamp_nanos() / 1_000_000) as i64)
+}
+
+/// MongoDB-backed session storage implementation
+///
+/// MongoDB's TTL monitor only runs about once a minute, so expiry is additionally
+/// enforced on every read.
+#[derive(Clone)]
+pub struct MongoSessionStorage {
+    /// Collection holding the session documents
+    pub collection: Collection<Document>,
+}
+
+impl MongoSessionStorage {
+    /// Creates a new MongoSessionStorage using the `rsession` collection of `db`
+    ///
+    /// The TTL index is created if it does not exist yet.
+    ///
+    /// # Arguments
+    /// * `db` - MongoDB database handle
+    ///
+    /// # Returns
+    /// Ok(MongoSessionStorage) if the index is ready, Err(Error) otherwise
+    pub async fn new(db: &Database) -> Result<Self, Error> {
+        MongoSessionStorage::with_collection(db.collection("rsession")).await
+    }
+    /// Creates a new MongoSessionStorage using a custom collection
+    ///
+    /// The TTL index is created if it does not exist yet. The collection should be
+    /// dedicated to sessions, as `clear` deletes every document in it.
+    ///
+    /// # Arguments
+    /// * `collection` - Collection to store the session documents in
+    pub async fn with_collection(collection: Collection<Document>) -> Result<Self, Error> {
+        let storage = MongoSessionStorage { collection };
+        storage.migrate().await?;
+        Ok(storage)
+    }
+
+    /// Creates the TTL index on `expiresAt` if it does not exist
+    pub async fn migrate(&self) -> Result<(), Error> {
+        let index = IndexModel::builder()
+            .keys(doc! { "expiresAt": 1 })
+            .options(
+                IndexOptions::builder()
+                    .expire_after(std::time::Duration::ZERO)
+                    .build(),
+            )
+            .build();
+        self.collection
+            .create_index(index)
+            .await
+            .map_err(Error::backend)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SessionStore for MongoSessionStorage {
+    /// Retrieves a session document from MongoDB
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to look up
+    ///
+    /// # Returns
+    /// Ok(Some(SessionInner)) if found and not expired, Ok(None) if missing or expired,
+    /// Err(Error) if the query fails or the document is malformed
+    async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error> {
+        let filter = doc! {
+            "_id": key,
+            "$or": [
+                { "expiresAt": Bson::Null },
+                { "expiresAt": { "$gt": DateTime::now() } },
+            ],
+        };
+        let document = self
+            .collection
+            .find_one(filter)
+            .await
+            .map_err(Error::backend)?;
+        let Some(document) = document else {
+            return Ok(None);
+        };
+        let map = document
+            .get_document("data")
+            .map_err(serde::de::Error::custom)
+            .and_then(|data| {
+                ::mongodb::bson::from_document::<HashMap<String, String>>(data.clone())
+                    .map_err(serde::de::Error::custom)
+            })
+            .map_err(Error::Serialization)?;
+        Ok(Some(SessionInner::loaded(key.to_string(), map)))
+    }
+
+    /// Stores a session document in MongoDB
+    ///
+    /// Replaces any existing document; like a Redis `SET`, this clears the expiry.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if storage fails
+    async fn set(&self, key: &str, value: SessionInner) -> Result<(), Error> {
+        let data = value
+            .data
+            .into_iter()
+            .map(|(name, value)| (name, Bson::String(value)))
+            .collect::<Document>();
+        self.collection
+            .replace_one(
+                doc! { "_id": key },
+                doc! { "_id": key, "data": data, "expiresAt": Bson::Null },
+            )
+            .upsert(true)
+            .await
+            .map_err(Error::backend)?;
+        Ok(())
+    }
+
+    /// Stores a session document in MongoDB together with its expiry
+    ///
+    /// A non-positive duration removes the session.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    /// * `ttl` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if storage fails
+    async fn save(&self, key: &str, value: SessionInner, ttl: Duration) -> Result<(), Error> {
+        if !ttl.is_positive() {
+            return self.remove(key).await;
+        }
+        let data = value
+            .data
+            .into_iter()
+            .map(|(name, value)| (name, Bson::String(value)))
+            .collect::<Document>();
+        self.collection
+            .replace_one(
+                doc! { "_id": key },
+                doc! { "_id": key, "data": data, "expiresAt": deadline(ttl) },
+            )
+            .upsert(true)
+            .await
+            .map_err(Error::backend)?;
+        Ok(())
+    }
+
+    /// Removes a session document from MongoDB
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to remove
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if removal fails
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        self.collection
+            .delete_one(doc! { "_id": key })
+            .await
+            .map_err(Error::backend)?;
+        Ok(())
+    }
+
+    /// Sets the `expiresAt` field of a session document
+    ///
+    /// A non-positive duration deletes the session immediately.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to update
+    /// * `expire_time` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if expiration update fails
+    async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), Error> {
+        if !expire_time.is_positive() {
+            return self.remove(key).await;
+        }
+        self.collection
+            .update_one(
+                doc! { "_id": key },
+                doc! { "$set": { "expiresAt": deadline(expire_time) } },
+            )
+            .await
+            .map_err(Error::backend)?;
+        Ok(())
+    }
+
+    /// Removes all session documents from this storage's collection
+    ///
+    /// # Returns
+    /// Ok(count) with the number of sessions removed, Err(Error) if clear operation fails
+    async fn clear(&self) -> Result<usize, Error> {
+        self.collection
+            .delete_many(doc! {})
+            .await
+            .map(|result| result.deleted_count as usize)
+            .map_err(Error::backend)
+    }
+}
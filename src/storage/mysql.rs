@@ -0,0 +1,310 @@
+//! MySQL / MariaDB session storage implementation
+//!
+//! This module provides a session store backed by a MySQL or MariaDB table, so that
+//! Rust services can share sessions with applications written in other languages.
+//! The table is created automatically with the following layout:
+//!
+//! ```sql
+//! CREATE TABLE rsession (
+//!     id         VARCHAR(255) CHARACTER SET ascii COLLATE ascii_bin NOT NULL PRIMARY KEY,
+//!     data       MEDIUMTEXT   NOT NULL,
+//!     expires_at BIGINT       NULL,
+//!     INDEX rsession_expires_at (expires_at)
+//! ) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_bin;
+//! ```
+//!
+//! * `id` is the session ID carried by the cookie (after signature verification).
+//!   ASCII IDs of at most 255 characters not starting with `~` are stored verbatim.
+//!   Any other ID, such as a `RandKey::Random(1024)` ID, is stored as `~` followed by
+//!   the hex SHA-256 of the ID, which other applications must compute the same way
+//!   to look it up. The 255-byte key stays within InnoDB's 767-byte index limit, so
+//!   the table can be created on MySQL 5.6 and with COMPACT or REDUNDANT row formats.
+//! * `data` is a JSON object mapping each session key to the JSON encoding of its
+//!   value, e.g. `{"user_id":"42","name":"\"alice\""}`. From PHP, read a value with
+//!   `json_decode(json_decode($row['data'], true)['name'])`.
+//! * `expires_at` is a unix timestamp in seconds computed with the database clock,
+//!   or NULL if the session never expires. A row is live while
+//!   `expires_at IS NULL OR expires_at > UNIX_TIMESTAMP()`.
+
+use crate::storage::{SessionStore, spawn_cleanup_task, valid_identifier};
+use crate::{Error, SessionInner};
+use async_trait::async_trait;
+use sqlx::Row;
+use sqlx::mysql::MySqlPool;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use time::Duration;
+use tokio::task::JoinHandle;
+
+/// Length of the `id` column, within InnoDB's 767-byte index key limit
+const MAX_ID_LEN: usize = 255;
+/// Prefix of `id` values holding the SHA-256 of a session ID
+const HASHED_PREFIX: char = '~';
+
+/// Returns the `id` column value of session `id`
+///
+/// IDs that are too long, not ASCII or start with the hashed prefix are replaced by
+/// the prefix and their SHA-256, so hashed values cannot collide with verbatim IDs.
+fn row_id(id: &str) -> Cow<'_, str> {
+    if id.len() <= MAX_ID_LEN && id.is_ascii() && !id.starts_with(HASHED_PREFIX) {
+        Cow::Borrowed(id)
+    } else {
+        Cow::Owned(format!("{}{}", HASHED_PREFIX, sha256::digest(id)))
+    }
+}
+
+/// MySQL-backed session storage implementation
+///
+/// Expiry is enforced on every read; expired rows are physically deleted by
+/// `cleanup`, which can be scheduled with `spawn_cleanup`.
+#[derive(Clone)]
+pub struct MySqlSessionStorage {
+    /// MySQL connection pool
+    pub pool: MySqlPool,
+    /// Name of the sessions table
+    pub table: String,
+}
+
+impl MySqlSessionStorage {
+    /// Creates a new MySqlSessionStorage using the `rsession` table
+    ///
+    /// The table is created if it does not exist yet.
+    ///
+    /// # Arguments
+    /// * `pool` - A sqlx MySQL connection pool
+    ///
+    /// # Returns
+    /// Ok(MySqlSessionStorage) if the schema is ready, Err(Error) otherwise
+    pub async fn new(pool: MySqlPool) -> Result<Self, Error> {
+        MySqlSessionStorage::with_table(pool, "rsession").await
+    }
+    /// Creates a new MySqlSessionStorage using a custom table name
+    ///
+    /// The table is created if it does not exist yet.
+    ///
+    /// # Arguments
+    /// * `pool` - A sqlx MySQL connection pool
+    /// * `table` - Table name made of ASCII letters, digits and underscores
+    ///
+    /// # Panics
+    /// Panics if the table name is not a plain SQL identifier
+    pub async fn with_table(pool: MySqlPool, table: &str) -> Result<Self, Error> {
+        assert!(
+            valid_identifier(table),
+            "table must be a plain SQL identifier"
+        );
+        let storage = MySqlSessionStorage {
+            pool,
+            table: table.to_string(),
+        };
+        storage.migrate().await?;
+        Ok(storage)
+    }
+
+    /// Creates the sessions table if it does not exist
+    pub async fn migrate(&self) -> Result<(), Error> {
+        sqlx::query(&format!(
+            "CREATE TABLE IF NOT EXISTS {0} (\
+                id VARCHAR(255) CHARACTER SET ascii COLLATE ascii_bin NOT NULL PRIMARY KEY, \
+                data MEDIUMTEXT NOT NULL, \
+                expires_at BIGINT NULL, \
+                INDEX {0}_expires_at (expires_at)\
+            ) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_bin",
+            self.table
+        ))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Deletes every expired session row
+    ///
+    /// # Returns
+    /// Ok(count) with the number of rows deleted, Err(Error) if the query fails
+    pub async fn cleanup(&self) -> Result<u64, Error> {
+        sqlx::query(&format!(
+            "DELETE FROM {} WHERE expires_at IS NOT NULL AND expires_at <= UNIX_TIMESTAMP()",
+            self.table
+        ))
+        .execute(&self.pool)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(Error::from)
+    }
+
+    /// Deletes expired rows every `interval` from a background task
+    ///
+    /// Abort the returned handle to stop it.
+    ///
+    /// # Panics
+    /// Panics if the interval is not positive or if called outside a tokio runtime
+    pub fn spawn_cleanup(&self, interval: Duration) -> JoinHandle<()> {
+        let storage = self.clone();
+        spawn_cleanup_task(interval, move || {
+            let storage = storage.clone();
+            async move { storage.cleanup().await }
+        })
+    }
+}
+
+#[async_trait]
+impl SessionStore for MySqlSessionStorage {
+    /// Retrieves a session from MySQL
+    ///
+    /// Rows whose expiry has passed are treated as missing.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to look up
+    ///
+    /// # Returns
+    /// Ok(Some(SessionInner)) if found and not expired, Ok(None) if missing or expired,
+    /// Err(Error) if the query fails
+    async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error> {
+        let row = sqlx::query(&format!(
+            "SELECT data FROM {} \
+             WHERE id = ? AND (expires_at IS NULL OR expires_at > UNIX_TIMESTAMP())",
+            self.table
+        ))
+        .bind(row_id(key))
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let data = row.try_get::<String, _>("data")?;
+        let map = serde_json::from_str::<HashMap<String, String>>(&data)?;
+        Ok(Some(SessionInner::loaded(key.to_string(), map)))
+    }
+
+    /// Stores a session in MySQL
+    ///
+    /// Inserts or replaces the row; like a Redis `SET`, this clears any expiry.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if serialization or storage fails
+    async fn set(&self, key: &str, value: SessionInner) -> Result<(), Error> {
+        let data = serde_json::to_string(&value.data)?;
+        sqlx::query(&format!(
+            "INSERT INTO {} (id, data, expires_at) VALUES (?, ?, NULL) \
+             ON DUPLICATE KEY UPDATE data = ?, expires_at = NULL",
+            self.table
+        ))
+        .bind(row_id(key))
+        .bind(&data)
+        .bind(&data)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Stores a session in MySQL together with its expiry in a single statement
+    ///
+    /// A non-positive duration removes the session.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    /// * `ttl` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if serialization or storage fails
+    async fn save(&self, key: &str, value: SessionInner, ttl: Duration) -> Result<(), Error> {
+        if !ttl.is_positive() {
+            return self.remove(key).await;
+        }
+        let data = serde_json::to_string(&value.data)?;
+        let seconds = ttl.whole_seconds() + i64::from(ttl.subsec_nanoseconds() > 0);
+        sqlx::query(&format!(
+            "INSERT INTO {} (id, data, expires_at) VALUES (?, ?, UNIX_TIMESTAMP() + ?) \
+             ON DUPLICATE KEY UPDATE data = ?, expires_at = UNIX_TIMESTAMP() + ?",
+            self.table
+        ))
+        .bind(row_id(key))
+        .bind(&data)
+        .bind(seconds)
+        .bind(&data)
+        .bind(seconds)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Removes a session from MySQL
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to remove
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if removal fails
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        sqlx::query(&format!("DELETE FROM {} WHERE id = ?", self.table))
+            .bind(row_id(key))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Sets expiration time for a session in MySQL
+    ///
+    /// The duration is rounded up to whole seconds. A non-positive duration deletes
+    /// the session immediately, like a Redis `EXPIRE`.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to update
+    /// * `expire_time` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if expiration update fails
+    async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), Error> {
+        if !expire_time.is_positive() {
+            return self.remove(key).await;
+        }
+        let seconds = expire_time.whole_seconds() + i64::from(expire_time.subsec_nanoseconds() > 0);
+        sqlx::query(&format!(
+            "UPDATE {} SET expires_at = UNIX_TIMESTAMP() + ? WHERE id = ?",
+            self.table
+        ))
+        .bind(seconds)
+        .bind(row_id(key))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Removes all sessions from this storage's table
+    ///
+    /// # Returns
+    /// Ok(count) with the number of sessions removed, Err(Error) if clear operation fails
+    async fn clear(&self) -> Result<usize, Error> {
+        sqlx::query(&format!("DELETE FROM {}", self.table))
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() as usize)
+            .map_err(Error::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn row_ids_fit_the_column_and_never_collide() {
+        assert_eq!(
+            row_id("0190a3f2-7c3e-7cc1-8f7a-2b3c4d5e6f70"),
+            "0190a3f2-7c3e-7cc1-8f7a-2b3c4d5e6f70"
+        );
+        assert_eq!(row_id(&"a".repeat(255)), "a".repeat(255));
+        for id in ["a".repeat(256), "a".repeat(1366), "é".to_string()] {
+            let row = row_id(&id);
+            assert!(row.starts_with(HASHED_PREFIX) && row.len() == 65);
+        }
+        // An ID shaped like a hashed value is hashed again rather than stored verbatim
+        let hashed = row_id(&"a".repeat(256)).into_owned();
+        assert_ne!(row_id(&hashed), hashed);
+    }
+}
@@ -0,0 +1,270 @@
+//! PostgreSQL session storage implementation
+//!
+//! This module provides a session store backed by a PostgreSQL table, for services
+//! that already run PostgreSQL and do not want to operate Redis only for sessions.
+//! The table is created automatically and holds one row per session:
+//!
+//! | column       | type                | content                                  |
+//! |--------------|---------------------|------------------------------------------|
+//! | `id`         | TEXT PRIMARY KEY    | session ID                               |
+//! | `data`       | JSONB               | JSON object of the session data          |
+//! | `expires_at` | TIMESTAMPTZ NULL    | expiry, NULL if the session never expires |
+//!
+//! Expiry is computed with the database clock, so application servers with skewed
+//! clocks still agree on when a session is gone.
+
+use crate::storage::{SessionStore, spawn_cleanup_task, valid_identifier};
+use crate::{Error, SessionInner};
+use async_trait::async_trait;
+use sqlx::Row;
+use sqlx::postgres::PgPool;
+use std::collections::HashMap;
+use time::Duration;
+use tokio::task::JoinHandle;
+
+/// PostgreSQL-backed session storage implementation
+///
+/// Expiry is enforced on every read; expired rows are physically deleted by
+/// `cleanup`, which can be scheduled with `spawn_cleanup`.
+#[derive(Clone)]
+pub struct PostgresSessionStorage {
+    /// PostgreSQL connection pool
+    pub pool: PgPool,
+    /// Schema holding the sessions table
+    pub schema: String,
+    /// Name of the sessions table
+    pub table: String,
+}
+
+impl PostgresSessionStorage {
+    /// Creates a new PostgresSessionStorage using the `public.rsession` table
+    ///
+    /// The table is created if it does not exist yet.
+    ///
+    /// # Arguments
+    /// * `pool` - A sqlx PostgreSQL connection pool
+    ///
+    /// # Returns
+    /// Ok(PostgresSessionStorage) if the schema is ready, Err(Error) otherwise
+    pub async fn new(pool: PgPool) -> Result<Self, Error> {
+        PostgresSessionStorage::with_table(pool, "public", "rsession").await
+    }
+    /// Creates a new PostgresSessionStorage using a custom schema and table
+    ///
+    /// The table is created if it does not exist yet; the schema must already exist.
+    ///
+    /// # Arguments
+    /// * `pool` - A sqlx PostgreSQL connection pool
+    /// * `schema` - Schema name made of ASCII letters, digits and underscores
+    /// * `table` - Table name made of ASCII letters, digits and underscores
+    ///
+    /// # Panics
+    /// Panics if the schema or table name is not a plain SQL identifier
+    pub async fn with_table(pool: PgPool, schema: &str, table: &str) -> Result<Self, Error> {
+        assert!(
+            valid_identifier(schema),
+            "schema must be a plain SQL identifier"
+        );
+        assert!(
+            valid_identifier(table),
+            "table must be a plain SQL identifier"
+        );
+        let storage = PostgresSessionStorage {
+            pool,
+            schema: schema.to_string(),
+            table: table.to_string(),
+        };
+        storage.migrate().await?;
+        Ok(storage)
+    }
+
+    /// Returns the schema-qualified table name
+    fn qualified(&self) -> String {
+        format!("{}.{}", self.schema, self.table)
+    }
+
+    /// Creates the sessions table and its expiry index if they do not exist
+    pub async fn migrate(&self) -> Result<(), Error> {
+        sqlx::query(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (\
+                id TEXT PRIMARY KEY NOT NULL, \
+                data JSONB NOT NULL, \
+                expires_at TIMESTAMPTZ NULL\
+            )",
+            self.qualified()
+        ))
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(&format!(
+            "CREATE INDEX IF NOT EXISTS {}_expires_at ON {} (expires_at)",
+            self.table,
+            self.qualified()
+        ))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Deletes every expired session row
+    ///
+    /// # Returns
+    /// Ok(count) with the number of rows deleted, Err(Error) if the query fails
+    pub async fn cleanup(&self) -> Result<u64, Error> {
+        sqlx::query(&format!(
+            "DELETE FROM {} WHERE expires_at IS NOT NULL AND expires_at <= now()",
+            self.qualified()
+        ))
+        .execute(&self.pool)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(Error::from)
+    }
+
+    /// Deletes expired rows every `interval` from a background task
+    ///
+    /// Abort the returned handle to stop it.
+    ///
+    /// # Panics
+    /// Panics if the interval is not positive or if called outside a tokio runtime
+    pub fn spawn_cleanup(&self, interval: Duration) -> JoinHandle<()> {
+        let storage = self.clone();
+        spawn_cleanup_task(interval, move || {
+            let storage = storage.clone();
+            async move { storage.cleanup().await }
+        })
+    }
+}
+
+#[async_trait]
+impl SessionStore for PostgresSessionStorage {
+    /// Retrieves a session from PostgreSQL
+    ///
+    /// Rows whose expiry has passed are treated as missing.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to look up
+    ///
+    /// # Returns
+    /// Ok(Some(SessionInner)) if found and not expired, Ok(None) if missing or expired,
+    /// Err(Error) if the query fails
+    async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error> {
+        let row = sqlx::query(&format!(
+            "SELECT data::text AS data FROM {} \
+             WHERE id = $1 AND (expires_at IS NULL OR expires_at > now())",
+            self.qualified()
+        ))
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let data = row.try_get::<String, _>("data")?;
+        let map = serde_json::from_str::<HashMap<String, String>>(&data)?;
+        Ok(Some(SessionInner::loaded(key.to_string(), map)))
+    }
+
+    /// Stores a session in PostgreSQL
+    ///
+    /// Inserts or replaces the row; like a Redis `SET`, this clears any expiry.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if serialization or storage fails
+    async fn set(&self, key: &str, value: SessionInner) -> Result<(), Error> {
+        sqlx::query(&format!(
+            "INSERT INTO {} (id, data, expires_at) VALUES ($1, $2::jsonb, NULL) \
+             ON CONFLICT (id) DO UPDATE SET data = excluded.data, expires_at = NULL",
+            self.qualified()
+        ))
+        .bind(key)
+        .bind(serde_json::to_string(&value.data)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Stores a session in PostgreSQL together with its expiry in a single statement
+    ///
+    /// A non-positive duration removes the session.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    /// * `ttl` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if serialization or storage fails
+    async fn save(&self, key: &str, value: SessionInner, ttl: Duration) -> Result<(), Error> {
+        if !ttl.is_positive() {
+            return self.remove(key).await;
+        }
+        sqlx::query(&format!(
+            "INSERT INTO {} (id, data, expires_at) \
+             VALUES ($1, $2::jsonb, now() + make_interval(secs => $3)) \
+             ON CONFLICT (id) DO UPDATE SET data = excluded.data, expires_at = excluded.expires_at",
+            self.qualified()
+        ))
+        .bind(key)
+        .bind(serde_json::to_string(&value.data)?)
+        .bind(ttl.as_seconds_f64())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Removes a session from PostgreSQL
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to remove
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if removal fails
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        sqlx::query(&format!("DELETE FROM {} WHERE id = $1", self.qualified()))
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Sets expiration time for a session in PostgreSQL
+    ///
+    /// A non-positive duration deletes the session immediately.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to update
+    /// * `expire_time` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if expiration update fails
+    async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), Error> {
+        if !expire_time.is_positive() {
+            return self.remove(key).await;
+        }
+        sqlx::query(&format!(
+            "UPDATE {} SET expires_at = now() + make_interval(secs => $1) WHERE id = $2",
+            self.qualified()
+        ))
+        .bind(expire_time.as_seconds_f64())
+        .bind(key)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Removes all sessions from this storage's table
+    ///
+    /// # Returns
+    /// Ok(count) with the number of sessions removed, Err(Error) if clear operation fails
+    async fn clear(&self) -> Result<usize, Error> {
+        sqlx::query(&format!("DELETE FROM {}", self.qualified()))
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() as usize)
+            .map_err(Error::from)
+    }
+}
@@ -0,0 +1,461 @@
+//! Embedded redb session storage implementation
+//!
+//! This module provides a session store persisted in a single redb database file.
+//! It needs no external service and sessions survive process restarts, which suits
+//! single-node deployments such as edge appliances.
+//!
+//! Two tables are kept in the database file:
+//! * `rsession_data` maps each session ID to the JSON object of its data, the same
+//!   document the Redis stores write
+//! * `rsession_expiry` maps each session ID with a TTL to its expiry deadline in
+//!   unix milliseconds
+
+// redb::Error is large, but it only lives until it is converted into rsession::Error
+#![allow(clippy::result_large_err)]
+
+use crate::storage::{SessionStore, now_millis, spawn_cleanup_task};
+use crate::{Error, SessionInner};
+use ::redb::{Database, ReadableTable, ReadableTableMetadata, TableDefinition};
+use async_trait::async_trait;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use time::Duration;
+use tokio::task::JoinHandle;
+
+/// Session ID to JSON-encoded session data
+const DATA: TableDefinition<&str, &str> = TableDefinition::new("rsession_data");
+/// Session ID to expiry deadline in unix milliseconds
+const EXPIRY: TableDefinition<&str, i64> = TableDefinition::new("rsession_expiry");
+
+/// redb-backed session storage implementation
+///
+/// Cloning is cheap and every clone shares the same database. All database work runs
+/// on tokio's blocking thread pool. Expiry is enforced on every read; expired entries
+/// are physically deleted by `cleanup`, which can be scheduled with `spawn_cleanup`.
+/// Deleting sessions does not shrink the file; `compact` does, and can be scheduled
+/// separately with `spawn_compaction`.
+#[derive(Clone)]
+pub struct RedbSessionStorage {
+    /// Shared database handle; the write lock is only taken for compaction
+    db: Arc<RwLock<Database>>,
+    /// Sessions deleted by `cleanup` and `clear` since the last compaction
+    deleted: Arc<AtomicU64>,
+}
+
+impl RedbSessionStorage {
+    /// Opens or creates the database file at `path`
+    ///
+    /// # Arguments
+    /// * `path` - Location of the database file
+    ///
+    /// # Returns
+    /// Ok(RedbSessionStorage) if the database is ready, Err(Error) otherwise
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let db = Database::create(path).map_err(Error::backend)?;
+        RedbSessionStorage::from_database(db)
+    }
+    /// Creates a RedbSessionStorage from an already opened database
+    ///
+    /// The session tables are created if they do not exist yet.
+    ///
+    /// # Arguments
+    /// * `db` - An open redb database
+    pub fn from_database(db: Database) -> Result<Self, Error> {
+        let txn = db.begin_write().map_err(Error::backend)?;
+        txn.open_table(DATA).map_err(Error::backend)?;
+        txn.open_table(EXPIRY).map_err(Error::backend)?;
+        txn.commit().map_err(Error::backend)?;
+        Ok(RedbSessionStorage {
+            db: Arc::new(RwLock::new(db)),
+            deleted: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// Runs a database operation on the blocking thread pool
+    async fn blocking<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Database) -> Result<T, ::redb::Error> + Send + 'static,
+    {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || f(&db.read()))
+            .await
+            .map_err(Error::backend)?
+            .map_err(Error::backend)
+    }
+
+    /// Deletes every expired session
+    ///
+    /// # Returns
+    /// Ok(count) with the number of sessions deleted, Err(Error) if the operation fails
+    pub async fn cleanup(&self) -> Result<u64, Error> {
+        let removed = self
+            .blocking(|db| {
+                let now = now_millis();
+                let txn = db.begin_write()?;
+                let removed = {
+                    let mut expiry = txn.open_table(EXPIRY)?;
+                    let mut data = txn.open_table(DATA)?;
+                    let expired = expiry
+                        .extract_if(|_, at| at <= now)?
+                        .map(|entry| entry.map(|(id, _)| id.value().to_string()))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    for id in &expired {
+                        data.remove(id.as_str())?;
+                    }
+                    expired.len() as u64
+                };
+                txn.commit()?;
+                Ok(removed)
+            })
+            .await?;
+        self.deleted.fetch_add(removed, Ordering::Relaxed);
+        Ok(removed)
+    }
+
+    /// Compacts the database file, returning space freed by deleted sessions
+    ///
+    /// Waits for in-flight operations to finish and blocks new ones while running.
+    ///
+    /// # Returns
+    /// Ok(true) if the file was compacted, Ok(false) if there was nothing to do
+    pub async fn compact(&self) -> Result<bool, Error> {
+        let deleted = self.deleted.load(Ordering::Relaxed);
+        let db = self.db.clone();
+        let compacted = tokio::task::spawn_blocking(move || db.write().compact())
+            .await
+            .map_err(Error::backend)?
+            .map_err(Error::backend)?;
+        self.deleted.fetch_sub(deleted, Ordering::Relaxed);
+        Ok(compacted)
+    }
+
+    /// Deletes expired entries every `interval` from a background task
+    ///
+    /// The file is not compacted; see `spawn_compaction`. Abort the returned handle
+    /// to stop it.
+    ///
+    /// # Panics
+    /// Panics if the interval is not positive or if called outside a tokio runtime
+    pub fn spawn_cleanup(&self, interval: Duration) -> JoinHandle<()> {
+        let storage = self.clone();
+        spawn_cleanup_task(interval, move || {
+            let storage = storage.clone();
+            async move { storage.cleanup().await }
+        })
+    }
+
+    /// Compacts the database file every `interval` from a background task
+    ///
+    /// Compaction blocks every session read and write while it runs, so a run is
+    /// skipped unless `cleanup` and `clear` deleted at least `min_deleted` sessions
+    /// since the last compaction. Pick an interval well above the cleanup interval,
+    /// such as once a day. Abort the returned handle to stop it.
+    ///
+    /// # Arguments
+    /// * `interval` - How often to check whether the file should be compacted
+    /// * `min_deleted` - Number of deleted sessions that makes compaction worthwhile
+    ///
+    /// # Panics
+    /// Panics if the interval is not positive or if called outside a tokio runtime
+    pub fn spawn_compaction(&self, interval: Duration, min_deleted: u64) -> JoinHandle<()> {
+        let storage = self.clone();
+        spawn_cleanup_task(interval, move || {
+            let storage = storage.clone();
+            async move {
+                let deleted = storage.deleted.load(Ordering::Relaxed);
+                if deleted < min_deleted.max(1) {
+                    return Ok(0);
+                }
+                storage.compact().await?;
+                Ok(deleted)
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl SessionStore for RedbSessionStorage {
+    /// Retrieves a session from the database
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to look up
+    ///
+    /// # Returns
+    /// Ok(Some(SessionInner)) if present and not expired, Ok(None) if missing or expired,
+    /// Err(Error) if the database fails
+    async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error> {
+        let id = key.to_string();
+        let data = self
+            .blocking(move |db| {
+                let txn = db.begin_read()?;
+                let expired = txn
+                    .open_table(EXPIRY)?
+                    .get(id.as_str())?
+                    .is_some_and(|at| at.value() <= now_millis());
+                if expired {
+                    return Ok(None);
+                }
+                let data = txn.open_table(DATA)?.get(id.as_str())?;
+                Ok(data.map(|data| data.value().to_string()))
+            })
+            .await?;
+        let Some(data) = data else {
+            return Ok(None);
+        };
+        let map = serde_json::from_str::<HashMap<String, String>>(&data)?;
+        Ok(Some(SessionInner::loaded(key.to_string(), map)))
+    }
+
+    /// Stores a session in the database
+    ///
+    /// Overwriting an existing session clears its expiry, matching the behaviour
+    /// of a plain Redis `SET`.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if serialization or storage fails
+    async fn set(&self, key: &str, value: SessionInner) -> Result<(), Error> {
+        let id = key.to_string();
+        let data = serde_json::to_string(&value.data)?;
+        self.blocking(move |db| {
+            let txn = db.begin_write()?;
+            txn.open_table(DATA)?.insert(id.as_str(), data.as_str())?;
+            txn.open_table(EXPIRY)?.remove(id.as_str())?;
+            txn.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Stores a session and its expiry in a single transaction
+    ///
+    /// A non-positive duration removes the session.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    /// * `ttl` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if serialization or storage fails
+    async fn save(&self, key: &str, value: SessionInner, ttl: Duration) -> Result<(), Error> {
+        if !ttl.is_positive() {
+            return self.remove(key).await;
+        }
+        let id = key.to_string();
+        let data = serde_json::to_string(&value.data)?;
+        let at = now_millis().saturating_add(ttl.whole_milliseconds() as i64);
+        self.blocking(move |db| {
+            let txn = db.begin_write()?;
+            txn.open_table(DATA)?.insert(id.as_str(), data.as_str())?;
+            txn.open_table(EXPIRY)?.insert(id.as_str(), at)?;
+            txn.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Removes a session from the database
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to remove
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if removal fails
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        let id = key.to_string();
+        self.blocking(move |db| {
+            let txn = db.begin_write()?;
+            txn.open_table(DATA)?.remove(id.as_str())?;
+            txn.open_table(EXPIRY)?.remove(id.as_str())?;
+            txn.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Sets expiration time for a session in the database
+    ///
+    /// A non-positive duration deletes the session immediately.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to update
+    /// * `expire_time` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if expiration update fails
+    async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), Error> {
+        if !expire_time.is_positive() {
+            return self.remove(key).await;
+        }
+        let id = key.to_string();
+        let at = now_millis().saturating_add(expire_time.whole_milliseconds() as i64);
+        self.blocking(move |db| {
+            let txn = db.begin_write()?;
+            if txn.open_table(DATA)?.get(id.as_str())?.is_some() {
+                txn.open_table(EXPIRY)?.insert(id.as_str(), at)?;
+            }
+            txn.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Removes all sessions held in the database
+    ///
+    /// # Returns
+    /// Ok(count) with the number of sessions removed, Err(Error) if clear operation fails
+    async fn clear(&self) -> Result<usize, Error> {
+        let removed = self
+            .blocking(|db| {
+                let txn = db.begin_write()?;
+                let removed = {
+                    let mut data = txn.open_table(DATA)?;
+                    let removed = data.len()?;
+                    data.retain(|_, _| false)?;
+                    txn.open_table(EXPIRY)?.retain(|_, _| false)?;
+                    removed
+                };
+                txn.commit()?;
+                Ok(removed)
+            })
+            .await?;
+        self.deleted.fetch_add(removed, Ordering::Relaxed);
+        Ok(removed as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::{session, user};
+
+    /// Opens a store on a database file private to the calling test
+    fn storage(name: &str) -> (RedbSessionStorage, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "rsession-redb-{}-{}.redb",
+            name,
+            std::process::id()
+        ));
+        std::fs::remove_file(&path).ok();
+        (RedbSessionStorage::open(&path).unwrap(), path)
+    }
+
+    #[tokio::test]
+    async fn sessions_and_expiry_survive_reopening() {
+        let (storage, path) = storage("reopen");
+        storage
+            .save("a", session("a", "alice"), Duration::minutes(5))
+            .await
+            .unwrap();
+        storage.set("b", session("b", "bob")).await.unwrap();
+        storage
+            .save("c", session("c", "carol"), Duration::milliseconds(50))
+            .await
+            .unwrap();
+        drop(storage);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let storage = RedbSessionStorage::open(&path).unwrap();
+        assert_eq!(user(&storage, "a").await.as_deref(), Some("alice"));
+        assert_eq!(user(&storage, "b").await.as_deref(), Some("bob"));
+        assert!(storage.get("c").await.unwrap().is_none());
+        assert_eq!(storage.cleanup().await.unwrap(), 1);
+        drop(storage);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cleanup_and_compaction_keep_live_sessions() {
+        let (storage, path) = storage("expiry");
+        storage
+            .save(
+                "short",
+                session("short", "alice"),
+                Duration::milliseconds(50),
+            )
+            .await
+            .unwrap();
+        storage
+            .save("long", session("long", "bob"), Duration::minutes(5))
+            .await
+            .unwrap();
+        // Expiring an unknown session does not create it
+        storage
+            .expire("missing", Duration::minutes(5))
+            .await
+            .unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(storage.get("short").await.unwrap().is_none());
+        assert!(storage.get("missing").await.unwrap().is_none());
+        assert_eq!(storage.cleanup().await.unwrap(), 1);
+        assert_eq!(storage.cleanup().await.unwrap(), 0);
+        storage.compact().await.unwrap();
+        assert_eq!(user(&storage, "long").await.as_deref(), Some("bob"));
+        drop(storage);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn spawned_cleanup_deletes_expired_sessions() {
+        let (storage, path) = storage("spawn_cleanup");
+        storage
+            .save("a", session("a", "alice"), Duration::milliseconds(20))
+            .await
+            .unwrap();
+        storage.set("b", session("b", "bob")).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(40)).await;
+        let task = storage.spawn_cleanup(Duration::milliseconds(10));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        task.abort();
+        assert_eq!(storage.cleanup().await.unwrap(), 0);
+        assert_eq!(user(&storage, "b").await.as_deref(), Some("bob"));
+        drop(storage);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn clear_removes_sessions_and_their_expiry() {
+        let (storage, path) = storage("clear");
+        storage
+            .save("a", session("a", "alice"), Duration::minutes(5))
+            .await
+            .unwrap();
+        storage.set("b", session("b", "bob")).await.unwrap();
+        assert_eq!(storage.clear().await.unwrap(), 2);
+        assert!(storage.get("a").await.unwrap().is_none());
+        assert!(storage.get("b").await.unwrap().is_none());
+        assert_eq!(storage.cleanup().await.unwrap(), 0);
+        drop(storage);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn spawned_compaction_waits_for_enough_deletions() {
+        let (storage, path) = storage("spawn_compaction");
+        for id in ["a", "b", "c"] {
+            storage
+                .save(id, session(id, "alice"), Duration::milliseconds(20))
+                .await
+                .unwrap();
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(40)).await;
+        assert_eq!(storage.cleanup().await.unwrap(), 3);
+        let task = storage.spawn_compaction(Duration::milliseconds(10), 5);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        task.abort();
+        assert_eq!(storage.deleted.load(Ordering::Relaxed), 3);
+        let task = storage.spawn_compaction(Duration::milliseconds(10), 3);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        task.abort();
+        assert_eq!(storage.deleted.load(Ordering::Relaxed), 0);
+        drop(storage);
+        std::fs::remove_file(path).unwrap();
+    }
+}
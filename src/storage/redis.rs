@@ -3,12 +3,13 @@
 //! This module provides a Redis-backed session store that implements the SessionStore trait.
 //! It supports basic session operations with optional key prefixing.
 
-use crate::storage::SessionStore;
-use crate::{RandKey, SessionInner};
+use crate::storage::{IdPage, SessionStore};
+use crate::{Error, SessionInner};
 use async_trait::async_trait;
-use deadpool_redis::redis::AsyncCommands;
+use deadpool_redis::redis;
+use deadpool_redis::redis::aio::ConnectionLike;
+use deadpool_redis::redis::{AsyncCommands, RedisResult};
 use std::collections::HashMap;
-use std::io::Error;
 use time::Duration;
 
 /// Redis-backed session storage implementation
@@ -18,7 +19,6 @@ use time::Duration;
 #[derive(Clone)]
 pub struct RedisSessionStorage {
     pub conn: deadpool_redis::Pool,
-    pub rand_key: RandKey,
     pub prefix: String,
 }
 
@@ -27,11 +27,9 @@ impl RedisSessionStorage {
     ///
     /// # Arguments
     /// * `pool` - A deadpool-redis connection pool
-    /// * `rand_key` - Strategy for generating random session IDs
-    pub fn new(pool: deadpool_redis::Pool, rand_key: RandKey) -> Self {
+    pub fn new(pool: deadpool_redis::Pool) -> Self {
         RedisSessionStorage {
             conn: pool,
-            rand_key,
             prefix: "".to_string(),
         }
     }
@@ -46,7 +44,6 @@ impl RedisSessionStorage {
         self.prefix = prefix.to_string();
         RedisSessionStorage {
             conn: self.conn.clone(),
-            rand_key: self.rand_key.clone(),
             prefix: self.prefix.clone(),
         }
     }
@@ -57,7 +54,7 @@ impl RedisSessionStorage {
     async fn get_conn(&self) -> Result<deadpool_redis::Connection, Error> {
         match self.conn.get().await {
             Ok(x) => Ok(x),
-            Err(x) => Err(Error::new(std::io::ErrorKind::Other, x)),
+            Err(x) => Err(Error::pool(x)),
         }
     }
 }
@@ -70,22 +67,19 @@ impl SessionStore for RedisSessionStorage {
     /// * `key` - Session ID to retrieve
     ///
     /// # Returns
-    /// A Result containing the SessionInner if found, or an error if retrieval fails
-    async fn get(&self, key: &str) -> Result<SessionInner, Error> {
+    /// Ok(Some(SessionInner)) if found, Ok(None) if the key does not exist,
+    /// Err(Error) if retrieval or deserialization fails
+    async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error> {
         let mut conn = self.get_conn().await?;
         let value = conn
-            .get::<&str, String>(&format!("{}{}", self.prefix, key))
+            .get::<_, Option<String>>(format!("{}{}", self.prefix, key))
             .await
-            .map_err(|err| Error::new(std::io::ErrorKind::Other, err));
-        let map = value
-            .map(|x| {
-                serde_json::from_str::<HashMap<String, String>>(&x)
-                    .map_err(|err| Error::new(std::io::ErrorKind::Other, err))
-            })?
-            .unwrap_or(HashMap::new());
-        let mut inner = SessionInner::new(self.rand_key.generate());
-        inner.data = map;
-        Ok(inner)
+            .map_err(Error::backend)?;
+        let Some(value) = value else {
+            return Ok(None);
+        };
+        let map = serde_json::from_str::<HashMap<String, String>>(&value)?;
+        Ok(Some(SessionInner::loaded(key.to_string(), map)))
     }
 
     /// Stores a session in Redis
@@ -99,11 +93,37 @@ impl SessionStore for RedisSessionStorage {
     async fn set(&self, key: &str, value: SessionInner) -> Result<(), Error> {
         let mut conn = self.get_conn().await?;
         conn.set(
-            &format!("{}{}", self.prefix, key),
+            format!("{}{}", self.prefix, key),
             serde_json::to_string(&value.data)?,
         )
         .await
-        .map_err(|err| Error::new(std::io::ErrorKind::Other, err))
+        .map_err(Error::backend)
+    }
+
+    /// Stores a session in Redis with its expiration in a single `SET EX`
+    ///
+    /// A duration shorter than one second removes the session.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    /// * `ttl` - Duration until the session expires
+    ///
+    /// # Returns
+    /// A Result indicating success or failure
+    async fn save(&self, key: &str, value: SessionInner, ttl: Duration) -> Result<(), Error> {
+        let seconds = match u64::try_from(ttl.whole_seconds()) {
+            Ok(seconds) if seconds > 0 => seconds,
+            _ => return self.remove(key).await,
+        };
+        let mut conn = self.get_conn().await?;
+        conn.set_ex(
+            format!("{}{}", self.prefix, key),
+            serde_json::to_string(&value.data)?,
+            seconds,
+        )
+        .await
+        .map_err(Error::backend)
     }
 
     /// Removes a session from Redis
@@ -115,9 +135,9 @@ impl SessionStore for RedisSessionStorage {
     /// A Result indicating success or failure
     async fn remove(&self, key: &str) -> Result<(), Error> {
         let mut conn = self.get_conn().await?;
-        conn.del(&format!("{}{}", self.prefix, key))
+        conn.del(format!("{}{}", self.prefix, key))
             .await
-            .map_err(|err| Error::new(std::io::ErrorKind::Other, err))
+            .map_err(Error::backend)
     }
 
     /// Sets an expiration time for a session in Redis
@@ -131,22 +151,274 @@ impl SessionStore for RedisSessionStorage {
     async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), Error> {
         let mut conn = self.get_conn().await?;
         conn.expire(
-            &format!("{}{}", self.prefix, key),
+            format!("{}{}", self.prefix, key),
             expire_time.as_seconds_f32() as i64,
         )
         .await
-        .map_err(|err| Error::new(std::io::ErrorKind::Other, err))
+        .map_err(Error::backend)
+    }
+
+    /// Clears all sessions stored under this storage's prefix
+    ///
+    /// Walks the keyspace incrementally with `SCAN MATCH <prefix>*` and deletes the
+    /// matches with `UNLINK`, so unrelated keys are left untouched and Redis is never
+    /// blocked for long. A store without a prefix refuses to clear, since every key
+    /// in the database would match.
+    ///
+    /// # Returns
+    /// The number of sessions removed, or Err(Error::Unsupported) if the prefix is empty
+    async fn clear(&self) -> Result<usize, Error> {
+        require_prefix(&self.prefix, "clear on a store without a key prefix")?;
+        let mut conn = self.get_conn().await?;
+        unlink_matching(&mut conn, &prefix_pattern(&self.prefix))
+            .await
+            .map_err(Error::backend)
+    }
+
+    /// Counts the sessions stored under this storage's prefix
+    ///
+    /// Walks the keyspace with `SCAN MATCH <prefix>*`. A store without a prefix
+    /// refuses to count, since every key in the database would match.
+    ///
+    /// # Returns
+    /// The number of sessions, or Err(Error::Unsupported) if the prefix is empty
+    async fn count(&self) -> Result<usize, Error> {
+        require_prefix(&self.prefix, "count on a store without a key prefix")?;
+        let mut conn = self.get_conn().await?;
+        count_matching(&mut conn, &prefix_pattern(&self.prefix))
+            .await
+            .map_err(Error::backend)
+    }
+
+    /// Lists one page of session IDs with a single `SCAN MATCH <prefix>*` round trip
+    ///
+    /// A store without a prefix refuses to list, since every key in the database
+    /// would match.
+    ///
+    /// # Arguments
+    /// * `cursor` - Cursor returned by the previous call, None for the first page
+    /// * `limit` - `COUNT` hint passed to `SCAN`
+    ///
+    /// # Returns
+    /// The IDs without the prefix and the next cursor, or Err(Error::Unsupported) if
+    /// the prefix is empty
+    async fn ids(&self, cursor: Option<&str>, limit: usize) -> Result<IdPage, Error> {
+        require_prefix(&self.prefix, "ids on a store without a key prefix")?;
+        let cursor = parse_cursor(cursor)?;
+        let mut conn = self.get_conn().await?;
+        let (next, keys) = scan_page(&mut conn, cursor, &prefix_pattern(&self.prefix), limit)
+            .await
+            .map_err(Error::backend)?;
+        Ok(IdPage {
+            ids: strip_prefix(&self.prefix, keys),
+            cursor: (next != 0).then(|| next.to_string()),
+        })
     }
 
-    /// Clears all sessions from Redis
+    /// Returns the remaining lifetime of a session using `PTTL`
+    ///
+    /// # Arguments
+    /// * `key` - Session ID to look up
     ///
-    /// WARNING: This will remove ALL keys in the Redis database (uses FLUSHALL)
-    /// Use with caution in production environments.
-    async fn clear(&self) -> Result<(), Error> {
+    /// # Returns
+    /// The remaining lifetime, None if the session never expires
+    async fn ttl(&self, key: &str) -> Result<Option<Duration>, Error> {
         let mut conn = self.get_conn().await?;
-        conn.flushall::<()>()
+        let millis = conn
+            .pttl::<_, i64>(format!("{}{}", self.prefix, key))
             .await
-            .map_err(|err| Error::new(std::io::ErrorKind::Other, err))?;
-        Ok(())
+            .map_err(Error::backend)?;
+        ttl_from_pttl(millis)
+    }
+}
+
+/// Number of keys requested per `SCAN` round trip
+pub(crate) const SCAN_COUNT: usize = 500;
+
+/// Returns an error if the store has no prefix to scope `operation` to
+///
+/// Without a prefix every key in the database would match, including data that does
+/// not belong to the session store, so `clear`, `count` and `ids` refuse to run.
+///
+/// # Arguments
+/// * `prefix` - Key prefix of the store
+/// * `operation` - Description of the refused operation reported in the error
+pub(crate) fn require_prefix(prefix: &str, operation: &'static str) -> Result<(), Error> {
+    if prefix.is_empty() {
+        return Err(Error::Unsupported(operation));
+    }
+    Ok(())
+}
+
+/// Builds a `SCAN MATCH` pattern matching every key that starts with `prefix`
+///
+/// Glob metacharacters in the prefix are escaped so they match literally.
+pub(crate) fn prefix_pattern(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for ch in prefix.chars() {
+        if matches!(ch, '*' | '?' | '[' | ']' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(ch);
+    }
+    pattern.push('*');
+    pattern
+}
+
+/// Deletes every key matching `pattern` on a single Redis node
+///
+/// # Arguments
+/// * `conn` - Connection to the node to scan
+/// * `pattern` - `SCAN MATCH` pattern selecting the keys to delete
+///
+/// # Returns
+/// The number of keys removed
+pub(crate) async fn unlink_matching<C>(conn: &mut C, pattern: &str) -> RedisResult<usize>
+where
+    C: ConnectionLike + Send,
+{
+    let mut cursor = 0u64;
+    let mut removed = 0;
+    loop {
+        let (next, keys) = scan_page(conn, cursor, pattern, SCAN_COUNT).await?;
+        if !keys.is_empty() {
+            removed += redis::cmd("UNLINK")
+                .arg(&keys)
+                .query_async::<usize>(conn)
+                .await?;
+        }
+        if next == 0 {
+            return Ok(removed);
+        }
+        cursor = next;
+    }
+}
+
+/// Counts every key matching `pattern` on a single Redis node
+///
+/// # Arguments
+/// * `conn` - Connection to the node to scan
+/// * `pattern` - `SCAN MATCH` pattern selecting the keys to count
+///
+/// # Returns
+/// The number of matching keys
+pub(crate) async fn count_matching<C>(conn: &mut C, pattern: &str) -> RedisResult<usize>
+where
+    C: ConnectionLike + Send,
+{
+    let mut cursor = 0u64;
+    let mut count = 0;
+    loop {
+        let (next, keys) = scan_page(conn, cursor, pattern, SCAN_COUNT).await?;
+        count += keys.len();
+        if next == 0 {
+            return Ok(count);
+        }
+        cursor = next;
+    }
+}
+
+/// Runs a single `SCAN cursor MATCH pattern COUNT count` round trip
+///
+/// # Returns
+/// The next cursor, 0 once the scan is complete, and the keys found
+pub(crate) async fn scan_page<C>(
+    conn: &mut C,
+    cursor: u64,
+    pattern: &str,
+    count: usize,
+) -> RedisResult<(u64, Vec<String>)>
+where
+    C: ConnectionLike + Send,
+{
+    redis::cmd("SCAN")
+        .arg(cursor)
+        .arg("MATCH")
+        .arg(pattern)
+        .arg("COUNT")
+        .arg(count.max(1))
+        .query_async(conn)
+        .await
+}
+
+/// Parses a `SCAN` cursor previously returned in an IdPage
+pub(crate) fn parse_cursor(cursor: Option<&str>) -> Result<u64, Error> {
+    match cursor {
+        None => Ok(0),
+        Some(cursor) => cursor
+            .parse()
+            .map_err(|_| Error::InvalidCursor(cursor.to_string())),
+    }
+}
+
+/// Removes the storage prefix from scanned keys
+pub(crate) fn strip_prefix(prefix: &str, keys: Vec<String>) -> Vec<String> {
+    keys.into_iter()
+        .filter_map(|key| key.strip_prefix(prefix).map(str::to_string))
+        .collect()
+}
+
+/// Converts a `PTTL` reply into the result of `SessionStore::ttl`
+///
+/// `PTTL` answers -2 for a missing key and -1 for a key without expiry.
+pub(crate) fn ttl_from_pttl(millis: i64) -> Result<Option<Duration>, Error> {
+    match millis {
+        -2 => Err(Error::NotFound),
+        millis if millis < 0 => Ok(None),
+        millis => Ok(Some(Duration::milliseconds(millis))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_pattern_escapes_glob_metacharacters() {
+        assert_eq!(prefix_pattern("rsession:"), "rsession:*");
+        assert_eq!(prefix_pattern(""), "*");
+        assert_eq!(prefix_pattern("a*b?c[d]e\\f"), "a\\*b\\?c\\[d\\]e\\\\f*");
+    }
+
+    #[test]
+    fn require_prefix_rejects_empty_prefixes() {
+        assert!(require_prefix("app:", "clear").is_ok());
+        assert!(matches!(
+            require_prefix("", "clear"),
+            Err(Error::Unsupported("clear"))
+        ));
+    }
+
+    #[test]
+    fn parse_cursor_accepts_scan_cursors_only() {
+        assert_eq!(parse_cursor(None).unwrap(), 0);
+        assert_eq!(parse_cursor(Some("1234")).unwrap(), 1234);
+        for cursor in ["", "-1", "abc", "0:1"] {
+            assert!(matches!(
+                parse_cursor(Some(cursor)),
+                Err(Error::InvalidCursor(value)) if value == cursor
+            ));
+        }
+    }
+
+    #[test]
+    fn strip_prefix_drops_foreign_keys() {
+        let keys = vec![
+            "app:a".to_string(),
+            "other:b".to_string(),
+            "app:".to_string(),
+        ];
+        assert_eq!(strip_prefix("app:", keys), ["a", ""]);
+    }
+
+    #[test]
+    fn ttl_from_pttl_maps_special_replies() {
+        assert!(matches!(ttl_from_pttl(-2), Err(Error::NotFound)));
+        assert_eq!(ttl_from_pttl(-1).unwrap(), None);
+        assert_eq!(ttl_from_pttl(0).unwrap(), Some(Duration::ZERO));
+        assert_eq!(
+            ttl_from_pttl(1500).unwrap(),
+            Some(Duration::milliseconds(1500))
+        );
     }
 }
@@ -2,12 +2,16 @@
 //!
 //! This module provides a session storage backend using Redis Cluster for distributed
 //! session management across multiple Redis nodes.
-use crate::storage::SessionStore;
-use crate::{RandKey, SessionInner};
+use crate::storage::redis::{
+    SCAN_COUNT, prefix_pattern, require_prefix, strip_prefix, ttl_from_pttl,
+};
+use crate::storage::{IdPage, SessionStore};
+use crate::{Error, SessionInner};
 use async_trait::async_trait;
+use deadpool_redis::redis;
 use deadpool_redis::redis::AsyncCommands;
+use deadpool_redis::redis::cluster_routing::{RoutingInfo, SingleNodeRoutingInfo};
 use std::collections::HashMap;
-use std::io::Error;
 use time::Duration;
 
 /// Redis Cluster session storage backend
@@ -18,21 +22,41 @@ use time::Duration;
 pub struct RedisClusterSessionStorage {
     /// Redis Cluster connection pool
     pub conn: deadpool_redis::cluster::Pool,
-    /// Session ID generation strategy
-    pub rand_key: RandKey,
     /// Key prefix for namespacing session keys in Redis
     pub prefix: String,
 }
 
 impl RedisClusterSessionStorage {
+    /// Creates a new RedisClusterSessionStorage instance without a key prefix
+    ///
+    /// # Arguments
+    /// * `pool` - A deadpool-redis cluster connection pool
+    pub fn new(pool: deadpool_redis::cluster::Pool) -> Self {
+        RedisClusterSessionStorage {
+            conn: pool,
+            prefix: "".to_string(),
+        }
+    }
+    /// Sets the key prefix for Redis Cluster storage
+    ///
+    /// All session keys will be prefixed with this string to avoid key collisions
+    /// in shared Redis environments. `clear`, `count` and `ids` refuse to run
+    /// without a prefix.
+    ///
+    /// # Arguments
+    /// * `prefix` - String to prepend to all Redis keys
+    pub fn set_prefix(&mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self.clone()
+    }
     /// Acquires a connection from the Redis Cluster pool
     ///
     /// # Returns
-    /// Ok(Connection) if successful, Err(io::Error) if connection fails
+    /// Ok(Connection) if successful, Err(Error) if connection fails
     async fn get_conn(&self) -> Result<deadpool_redis::cluster::Connection, Error> {
         match self.conn.get().await {
             Ok(x) => Ok(x),
-            Err(x) => Err(Error::new(std::io::ErrorKind::Other, x)),
+            Err(x) => Err(Error::pool(x)),
         }
     }
 }
@@ -45,22 +69,19 @@ impl SessionStore for RedisClusterSessionStorage {
     /// * `key` - Session identifier to look up
     ///
     /// # Returns
-    /// Ok(SessionInner) if found and deserialized successfully, Err(io::Error) otherwise
-    async fn get(&self, key: &str) -> Result<SessionInner, Error> {
+    /// Ok(Some(SessionInner)) if found, Ok(None) if the key does not exist,
+    /// Err(Error) if retrieval or deserialization fails
+    async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error> {
         let mut conn = self.get_conn().await?;
         let value = conn
-            .get::<&str, String>(&format!("{}{}", self.prefix, key))
+            .get::<_, Option<String>>(format!("{}{}", self.prefix, key))
             .await
-            .map_err(|err| Error::new(std::io::ErrorKind::Other, err));
-        let map = value
-            .map(|x| {
-                serde_json::from_str::<HashMap<String, String>>(&x)
-                    .map_err(|err| Error::new(std::io::ErrorKind::Other, err))
-            })?
-            .unwrap_or(HashMap::new());
-        let mut inner = SessionInner::new(self.rand_key.generate());
-        inner.data = map;
-        Ok(inner)
+            .map_err(Error::backend)?;
+        let Some(value) = value else {
+            return Ok(None);
+        };
+        let map = serde_json::from_str::<HashMap<String, String>>(&value)?;
+        Ok(Some(SessionInner::loaded(key.to_string(), map)))
     }
 
     /// Stores a session in Redis Cluster
@@ -70,28 +91,55 @@ impl SessionStore for RedisClusterSessionStorage {
     /// * `value` - SessionInner instance containing the data to store
     ///
     /// # Returns
-    /// Ok(()) if successful, Err(io::Error) if serialization or storage fails
+    /// Ok(()) if successful, Err(Error) if serialization or storage fails
     async fn set(&self, key: &str, value: SessionInner) -> Result<(), Error> {
         let mut conn = self.get_conn().await?;
         conn.set(
-            &format!("{}{}", self.prefix, key),
+            format!("{}{}", self.prefix, key),
+            serde_json::to_string(&value.data)?,
+        )
+        .await
+        .map_err(Error::backend)
+    }
+
+    /// Stores a session in Redis Cluster with its expiration in a single `SET EX`
+    ///
+    /// A duration shorter than one second removes the session.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    /// * `ttl` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if serialization or storage fails
+    async fn save(&self, key: &str, value: SessionInner, ttl: Duration) -> Result<(), Error> {
+        let seconds = match u64::try_from(ttl.whole_seconds()) {
+            Ok(seconds) if seconds > 0 => seconds,
+            _ => return self.remove(key).await,
+        };
+        let mut conn = self.get_conn().await?;
+        conn.set_ex(
+            format!("{}{}", self.prefix, key),
             serde_json::to_string(&value.data)?,
+            seconds,
         )
         .await
-        .map_err(|err| Error::new(std::io::ErrorKind::Other, err))
+        .map_err(Error::backend)
     }
+
     /// Removes a session from Redis Cluster
     ///
     /// # Arguments
     /// * `key` - Session identifier to remove
     ///
     /// # Returns
-    /// Ok(()) if successful, Err(io::Error) if removal fails
+    /// Ok(()) if successful, Err(Error) if removal fails
     async fn remove(&self, key: &str) -> Result<(), Error> {
         let mut conn = self.get_conn().await?;
-        conn.del(&format!("{}{}", self.prefix, key))
+        conn.del(format!("{}{}", self.prefix, key))
             .await
-            .map_err(|err| Error::new(std::io::ErrorKind::Other, err))
+            .map_err(Error::backend)
     }
 
     /// Sets expiration time for a session in Redis Cluster
@@ -101,30 +149,261 @@ impl SessionStore for RedisClusterSessionStorage {
     /// * `expire_time` - Duration until the session expires
     ///
     /// # Returns
-    /// Ok(()) if successful, Err(io::Error) if expiration update fails
+    /// Ok(()) if successful, Err(Error) if expiration update fails
     async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), Error> {
         let mut conn = self.get_conn().await?;
         conn.expire(
-            &format!("{}{}", self.prefix, key),
+            format!("{}{}", self.prefix, key),
             expire_time.as_seconds_f32() as i64,
         )
         .await
-        .map_err(|err| Error::new(std::io::ErrorKind::Other, err))
+        .map_err(Error::backend)
+    }
+
+    /// Removes all sessions stored under this storage's prefix from Redis Cluster
+    ///
+    /// Runs incremental `SCAN MATCH <prefix>*` on every primary node and deletes the
+    /// matches with `UNLINK`, so unrelated keys are left untouched. A store without
+    /// a prefix refuses to clear, since every key in the cluster would match.
+    ///
+    /// # Returns
+    /// Ok(count) with the number of sessions removed, Err(Error::Unsupported) if the
+    /// prefix is empty, Err(Error) if the operation fails
+    async fn clear(&self) -> Result<usize, Error> {
+        require_prefix(&self.prefix, "clear on a store without a key prefix")?;
+        let mut conn = self.get_conn().await?;
+        let pattern = prefix_pattern(&self.prefix);
+        let mut removed = 0;
+        for node in primaries(&mut conn).await? {
+            let mut cursor = 0u64;
+            loop {
+                let (next, keys) =
+                    scan_node(&mut conn, &node, cursor, &pattern, SCAN_COUNT).await?;
+                if !keys.is_empty() {
+                    removed += redis::cmd("UNLINK")
+                        .arg(&keys)
+                        .query_async::<usize>(&mut conn)
+                        .await
+                        .map_err(Error::backend)?;
+                }
+                if next == 0 {
+                    break;
+                }
+                cursor = next;
+            }
+        }
+        Ok(removed)
     }
 
-    /// Removes all sessions from Redis Cluster
+    /// Counts the sessions stored under this storage's prefix on every primary node
     ///
-    /// # Warning
-    /// This is a destructive operation that will clear ALL data in the Redis Cluster
-    /// using FLUSHALL
+    /// A store without a prefix refuses to count, since every key would match.
+    ///
+    /// # Returns
+    /// Ok(count) with the number of sessions, Err(Error::Unsupported) if the prefix
+    /// is empty, Err(Error) if a scan fails
+    async fn count(&self) -> Result<usize, Error> {
+        require_prefix(&self.prefix, "count on a store without a key prefix")?;
+        let mut conn = self.get_conn().await?;
+        let pattern = prefix_pattern(&self.prefix);
+        let mut count = 0;
+        for node in primaries(&mut conn).await? {
+            let mut cursor = 0u64;
+            loop {
+                let (next, keys) =
+                    scan_node(&mut conn, &node, cursor, &pattern, SCAN_COUNT).await?;
+                count += keys.len();
+                if next == 0 {
+                    break;
+                }
+                cursor = next;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Lists one page of session IDs, scanning the primary nodes one after another
+    ///
+    /// Each call runs a single `SCAN` on one node; the cursor records the node and its
+    /// scan position. Changes to the cluster topology during the listing may cause
+    /// IDs to be skipped or repeated.
+    ///
+    /// # Arguments
+    /// * `cursor` - Cursor returned by the previous call, None for the first page
+    /// * `limit` - `COUNT` hint passed to `SCAN`
     ///
     /// # Returns
-    /// Ok(()) if successful, Err(io::Error) if clear operation fails
-    async fn clear(&self) -> Result<(), Error> {
+    /// Ok(IdPage) with the IDs without the prefix, Err(Error::Unsupported) if the
+    /// prefix is empty, Err(Error) if the scan fails
+    async fn ids(&self, cursor: Option<&str>, limit: usize) -> Result<IdPage, Error> {
+        require_prefix(&self.prefix, "ids on a store without a key prefix")?;
+        let (index, cursor) = parse_node_cursor(cursor)?;
         let mut conn = self.get_conn().await?;
-        conn.flushall::<()>()
+        let nodes = primaries(&mut conn).await?;
+        let Some(node) = nodes.get(index) else {
+            return Ok(IdPage::default());
+        };
+        let pattern = prefix_pattern(&self.prefix);
+        let (next, keys) = scan_node(&mut conn, node, cursor, &pattern, limit).await?;
+        Ok(IdPage {
+            ids: strip_prefix(&self.prefix, keys),
+            cursor: next_node_cursor(index, next, nodes.len()),
+        })
+    }
+
+    /// Returns the remaining lifetime of a session using `PTTL`
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to look up
+    ///
+    /// # Returns
+    /// Ok(Some(duration)) if the session expires, Ok(None) if it never expires,
+    /// Err(Error::NotFound) if it does not exist
+    async fn ttl(&self, key: &str) -> Result<Option<Duration>, Error> {
+        let mut conn = self.get_conn().await?;
+        let millis = conn
+            .pttl::<_, i64>(format!("{}{}", self.prefix, key))
             .await
-            .map_err(|err| Error::new(std::io::ErrorKind::Other, err))?;
-        Ok(())
+            .map_err(Error::backend)?;
+        ttl_from_pttl(millis)
+    }
+}
+
+/// Parses an `ids` cursor of the form `{node index}:{scan cursor}`
+///
+/// # Returns
+/// Ok((index, cursor)), (0, 0) for the first page, or Err(Error::InvalidCursor) if the
+/// cursor was not issued by `ids`
+fn parse_node_cursor(cursor: Option<&str>) -> Result<(usize, u64), Error> {
+    match cursor {
+        None => Ok((0, 0)),
+        Some(value) => value
+            .split_once(':')
+            .and_then(|(index, cursor)| Some((index.parse().ok()?, cursor.parse().ok()?)))
+            .ok_or_else(|| Error::InvalidCursor(value.to_string())),
+    }
+}
+
+/// Builds the `ids` cursor following a `SCAN` of node `index` that returned `next`
+///
+/// Moves on to the next node once a node is fully scanned, and returns None after
+/// the last one.
+fn next_node_cursor(index: usize, next: u64, nodes: usize) -> Option<String> {
+    match next {
+        0 if index + 1 < nodes => Some(format!("{}:0", index + 1)),
+        0 => None,
+        next => Some(format!("{index}:{next}")),
+    }
+}
+
+/// Runs a single `SCAN` round trip on one cluster node
+///
+/// # Arguments
+/// * `conn` - Cluster connection used to route the command
+/// * `node` - Address of the node to scan
+/// * `cursor` - Scan position on that node, 0 to start
+/// * `pattern` - `SCAN MATCH` pattern selecting the keys
+/// * `count` - `COUNT` hint for the number of keys to return
+///
+/// # Returns
+/// The next cursor, 0 once the node is fully scanned, and the keys found
+async fn scan_node(
+    conn: &mut deadpool_redis::cluster::Connection,
+    node: &(String, u16),
+    cursor: u64,
+    pattern: &str,
+    count: usize,
+) -> Result<(u64, Vec<String>), Error> {
+    let (host, port) = node.clone();
+    let route = RoutingInfo::SingleNode(SingleNodeRoutingInfo::ByAddress { host, port });
+    let mut scan = redis::cmd("SCAN");
+    scan.arg(cursor)
+        .arg("MATCH")
+        .arg(pattern)
+        .arg("COUNT")
+        .arg(count.max(1));
+    let value = conn
+        .route_command(&scan, route)
+        .await
+        .map_err(Error::backend)?;
+    redis::from_redis_value(&value).map_err(Error::backend)
+}
+
+/// Lists the address of every reachable primary node in the cluster
+///
+/// The addresses are sorted so that paginated scans visit the nodes in a stable order.
+///
+/// # Returns
+/// Ok(Vec<(host, port)>) parsed from `CLUSTER NODES`, Err(Error) if the query fails
+async fn primaries(
+    conn: &mut deadpool_redis::cluster::Connection,
+) -> Result<Vec<(String, u16)>, Error> {
+    let nodes: String = redis::cmd("CLUSTER")
+        .arg("NODES")
+        .query_async(conn)
+        .await
+        .map_err(Error::backend)?;
+    let mut primaries = nodes.lines().filter_map(parse_primary).collect::<Vec<_>>();
+    primaries.sort();
+    Ok(primaries)
+}
+
+/// Parses one `CLUSTER NODES` line, returning the address if it describes a healthy primary
+fn parse_primary(line: &str) -> Option<(String, u16)> {
+    let mut fields = line.split_whitespace();
+    let address = fields.nth(1)?;
+    let flags = fields.next()?.split(',').collect::<Vec<_>>();
+    if !flags.contains(&"master") || flags.contains(&"fail") || flags.contains(&"noaddr") {
+        return None;
+    }
+    let (host, port) = address.split(['@', ',']).next()?.rsplit_once(':')?;
+    if host.is_empty() {
+        return None;
+    }
+    Some((host.to_string(), port.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_primary_keeps_healthy_primaries_only() {
+        let nodes = "\
+07c37dfeb235213a872192d90877d0cd55635b91 127.0.0.1:30004@31004,hostname4 slave e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 0 1426238317239 4 connected
+67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 127.0.0.1:30002@31002,hostname2 master - 0 1426238316232 2 connected 5461-10922
+292f8b365bb7edb5e285caf0b7e6ddc7265d2f4f 127.0.0.1:30003@31003 master - 0 1426238318243 3 connected 10923-16383
+6ec23923021cf3ffec47632106199cb7f496ce01 127.0.0.1:30005@31005 master,fail - 1426238316232 0 5 disconnected
+824fe116063bc5fcf9f4ffd895bc17aee7731ac3 :0@0 master,noaddr - 1426238318243 0 6 disconnected
+e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 10.0.0.1:30001 myself,master - 0 0 1 connected 0-5460
+a8a8a8a8a8a8a8a8a8a8a8a8a8a8a8a8a8a8a8a8 [::1]:30006@31006 master - 0 0 7 connected";
+        let primaries = nodes.lines().filter_map(parse_primary).collect::<Vec<_>>();
+        assert_eq!(
+            primaries,
+            [
+                ("127.0.0.1".to_string(), 30002),
+                ("127.0.0.1".to_string(), 30003),
+                ("10.0.0.1".to_string(), 30001),
+                ("[::1]".to_string(), 30006),
+            ]
+        );
+        assert_eq!(parse_primary(""), None);
+        assert_eq!(parse_primary("id 127.0.0.1:x master"), None);
+    }
+
+    #[test]
+    fn node_cursors_round_trip() {
+        assert_eq!(parse_node_cursor(None).unwrap(), (0, 0));
+        assert_eq!(next_node_cursor(0, 17, 3).as_deref(), Some("0:17"));
+        assert_eq!(next_node_cursor(0, 0, 3).as_deref(), Some("1:0"));
+        assert_eq!(next_node_cursor(2, 0, 3), None);
+        assert_eq!(parse_node_cursor(Some("0:17")).unwrap(), (0, 17));
+        assert_eq!(parse_node_cursor(Some("1:0")).unwrap(), (1, 0));
+        for cursor in ["", "17", "a:1", "1:a", "-1:0", "1:2:3"] {
+            assert!(matches!(
+                parse_node_cursor(Some(cursor)),
+                Err(Error::InvalidCursor(value)) if value == cursor
+            ));
+        }
     }
 }
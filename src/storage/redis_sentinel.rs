@@ -2,12 +2,15 @@
 //!
 //! This module provides a session storage backend using Redis Sentinel for high-availability
 //! session management with automatic failover support.
-use crate::storage::SessionStore;
-use crate::{RandKey, SessionInner};
+use crate::storage::redis::{
+    count_matching, parse_cursor, prefix_pattern, require_prefix, scan_page, strip_prefix,
+    ttl_from_pttl, unlink_matching,
+};
+use crate::storage::{IdPage, SessionStore};
+use crate::{Error, SessionInner};
 use async_trait::async_trait;
 use deadpool_redis::redis::AsyncCommands;
 use std::collections::HashMap;
-use std::io::Error;
 use time::Duration;
 
 /// Redis Sentinel session storage backend
@@ -18,21 +21,41 @@ use time::Duration;
 pub struct RedisSentinelSessionStorage {
     /// Redis Sentinel connection pool
     pub conn: deadpool_redis::sentinel::Pool,
-    /// Session ID generation strategy
-    pub rand_key: RandKey,
     /// Key prefix for namespacing session keys in Redis
     pub prefix: String,
 }
 
 impl RedisSentinelSessionStorage {
+    /// Creates a new RedisSentinelSessionStorage instance without a key prefix
+    ///
+    /// # Arguments
+    /// * `pool` - A deadpool-redis sentinel connection pool
+    pub fn new(pool: deadpool_redis::sentinel::Pool) -> Self {
+        RedisSentinelSessionStorage {
+            conn: pool,
+            prefix: "".to_string(),
+        }
+    }
+    /// Sets the key prefix for Redis Sentinel storage
+    ///
+    /// All session keys will be prefixed with this string to avoid key collisions
+    /// in shared Redis environments. `clear`, `count` and `ids` refuse to run
+    /// without a prefix.
+    ///
+    /// # Arguments
+    /// * `prefix` - String to prepend to all Redis keys
+    pub fn set_prefix(&mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self.clone()
+    }
     /// Acquires a connection from the Redis Sentinel pool
     ///
     /// # Returns
-    /// Ok(Connection) if successful, Err(io::Error) if connection fails
+    /// Ok(Connection) if successful, Err(Error) if connection fails
     async fn get_conn(&self) -> Result<deadpool_redis::sentinel::Connection, Error> {
         match self.conn.get().await {
             Ok(x) => Ok(x),
-            Err(x) => Err(Error::new(std::io::ErrorKind::Other, x)),
+            Err(x) => Err(Error::pool(x)),
         }
     }
 }
@@ -45,22 +68,19 @@ impl SessionStore for RedisSentinelSessionStorage {
     /// * `key` - Session identifier to look up
     ///
     /// # Returns
-    /// Ok(SessionInner) if found and deserialized successfully, Err(io::Error) otherwise
-    async fn get(&self, key: &str) -> Result<SessionInner, Error> {
+    /// Ok(Some(SessionInner)) if found, Ok(None) if the key does not exist,
+    /// Err(Error) if retrieval or deserialization fails
+    async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error> {
         let mut conn = self.get_conn().await?;
         let value = conn
-            .get::<&str, String>(&format!("{}{}", self.prefix, key))
+            .get::<_, Option<String>>(format!("{}{}", self.prefix, key))
             .await
-            .map_err(|err| Error::new(std::io::ErrorKind::Other, err));
-        let map = value
-            .map(|x| {
-                serde_json::from_str::<HashMap<String, String>>(&x)
-                    .map_err(|err| Error::new(std::io::ErrorKind::Other, err))
-            })?
-            .unwrap_or(HashMap::new());
-        let mut inner = SessionInner::new(self.rand_key.generate());
-        inner.data = map;
-        Ok(inner)
+            .map_err(Error::backend)?;
+        let Some(value) = value else {
+            return Ok(None);
+        };
+        let map = serde_json::from_str::<HashMap<String, String>>(&value)?;
+        Ok(Some(SessionInner::loaded(key.to_string(), map)))
     }
 
     /// Stores a session in Redis Sentinel
@@ -70,15 +90,41 @@ impl SessionStore for RedisSentinelSessionStorage {
     /// * `value` - SessionInner instance containing the data to store
     ///
     /// # Returns
-    /// Ok(()) if successful, Err(io::Error) if serialization or storage fails
+    /// Ok(()) if successful, Err(Error) if serialization or storage fails
     async fn set(&self, key: &str, value: SessionInner) -> Result<(), Error> {
         let mut conn = self.get_conn().await?;
         conn.set(
-            &format!("{}{}", self.prefix, key),
+            format!("{}{}", self.prefix, key),
             serde_json::to_string(&value.data)?,
         )
         .await
-        .map_err(|err| Error::new(std::io::ErrorKind::Other, err))
+        .map_err(Error::backend)
+    }
+
+    /// Stores a session in Redis Sentinel with its expiration in a single `SET EX`
+    ///
+    /// A duration shorter than one second removes the session.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    /// * `ttl` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if serialization or storage fails
+    async fn save(&self, key: &str, value: SessionInner, ttl: Duration) -> Result<(), Error> {
+        let seconds = match u64::try_from(ttl.whole_seconds()) {
+            Ok(seconds) if seconds > 0 => seconds,
+            _ => return self.remove(key).await,
+        };
+        let mut conn = self.get_conn().await?;
+        conn.set_ex(
+            format!("{}{}", self.prefix, key),
+            serde_json::to_string(&value.data)?,
+            seconds,
+        )
+        .await
+        .map_err(Error::backend)
     }
 
     /// Removes a session from Redis Sentinel
@@ -87,12 +133,12 @@ impl SessionStore for RedisSentinelSessionStorage {
     /// * `key` - Session identifier to remove
     ///
     /// # Returns
-    /// Ok(()) if successful, Err(io::Error) if removal fails
+    /// Ok(()) if successful, Err(Error) if removal fails
     async fn remove(&self, key: &str) -> Result<(), Error> {
         let mut conn = self.get_conn().await?;
-        conn.del(&format!("{}{}", self.prefix, key))
+        conn.del(format!("{}{}", self.prefix, key))
             .await
-            .map_err(|err| Error::new(std::io::ErrorKind::Other, err))
+            .map_err(Error::backend)
     }
 
     /// Sets expiration time for a session in Redis Sentinel
@@ -102,29 +148,88 @@ impl SessionStore for RedisSentinelSessionStorage {
     /// * `expire_time` - Duration until the session expires
     ///
     /// # Returns
-    /// Ok(()) if successful, Err(io::Error) if expiration update fails
+    /// Ok(()) if successful, Err(Error) if expiration update fails
     async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), Error> {
         let mut conn = self.get_conn().await?;
         conn.expire(
-            &format!("{}{}", self.prefix, key),
+            format!("{}{}", self.prefix, key),
             expire_time.as_seconds_f32() as i64,
         )
         .await
-        .map_err(|err| Error::new(std::io::ErrorKind::Other, err))
+        .map_err(Error::backend)
+    }
+
+    /// Removes all sessions stored under this storage's prefix from Redis Sentinel
+    ///
+    /// Uses incremental `SCAN MATCH <prefix>*` and `UNLINK` on the current master,
+    /// so unrelated keys are left untouched. A store without a prefix refuses to
+    /// clear, since every key would match.
+    ///
+    /// # Returns
+    /// Ok(count) with the number of sessions removed, Err(Error::Unsupported) if the
+    /// prefix is empty, Err(Error) if the operation fails
+    async fn clear(&self) -> Result<usize, Error> {
+        require_prefix(&self.prefix, "clear on a store without a key prefix")?;
+        let mut conn = self.get_conn().await?;
+        unlink_matching(&mut conn, &prefix_pattern(&self.prefix))
+            .await
+            .map_err(Error::backend)
+    }
+
+    /// Counts the sessions stored under this storage's prefix on the current master
+    ///
+    /// A store without a prefix refuses to count, since every key would match.
+    ///
+    /// # Returns
+    /// Ok(count) with the number of sessions, Err(Error::Unsupported) if the prefix
+    /// is empty, Err(Error) if the scan fails
+    async fn count(&self) -> Result<usize, Error> {
+        require_prefix(&self.prefix, "count on a store without a key prefix")?;
+        let mut conn = self.get_conn().await?;
+        count_matching(&mut conn, &prefix_pattern(&self.prefix))
+            .await
+            .map_err(Error::backend)
     }
 
-    /// Removes all sessions from Redis Sentinel
+    /// Lists one page of session IDs with a single `SCAN` on the current master
     ///
-    /// # Warning
-    /// This is a destructive operation that will clear ALL data in Redis using FLUSHALL
+    /// A failover to another master during the listing restarts the cursor space;
+    /// start over from the first page if IDs are missing.
+    ///
+    /// # Arguments
+    /// * `cursor` - Cursor returned by the previous call, None for the first page
+    /// * `limit` - `COUNT` hint passed to `SCAN`
+    ///
+    /// # Returns
+    /// Ok(IdPage) with the IDs without the prefix, Err(Error::Unsupported) if the
+    /// prefix is empty, Err(Error) if the scan fails
+    async fn ids(&self, cursor: Option<&str>, limit: usize) -> Result<IdPage, Error> {
+        require_prefix(&self.prefix, "ids on a store without a key prefix")?;
+        let cursor = parse_cursor(cursor)?;
+        let mut conn = self.get_conn().await?;
+        let (next, keys) = scan_page(&mut conn, cursor, &prefix_pattern(&self.prefix), limit)
+            .await
+            .map_err(Error::backend)?;
+        Ok(IdPage {
+            ids: strip_prefix(&self.prefix, keys),
+            cursor: (next != 0).then(|| next.to_string()),
+        })
+    }
+
+    /// Returns the remaining lifetime of a session using `PTTL`
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to look up
     ///
     /// # Returns
-    /// Ok(()) if successful, Err(io::Error) if clear operation fails
-    async fn clear(&self) -> Result<(), Error> {
+    /// Ok(Some(duration)) if the session expires, Ok(None) if it never expires,
+    /// Err(Error::NotFound) if it does not exist
+    async fn ttl(&self, key: &str) -> Result<Option<Duration>, Error> {
         let mut conn = self.get_conn().await?;
-        conn.flushall::<()>()
+        let millis = conn
+            .pttl::<_, i64>(format!("{}{}", self.prefix, key))
             .await
-            .map_err(|err| Error::new(std::io::ErrorKind::Other, err))?;
-        Ok(())
+            .map_err(Error::backend)?;
+        ttl_from_pttl(millis)
     }
 }
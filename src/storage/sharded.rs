@@ -0,0 +1,318 @@
+//! Sharded session storage
+//!
+//! This module provides a wrapper spreading sessions over several independent
+//! SessionStore instances, such as multiple standalone Redis servers. Session IDs are
+//! routed with consistent hashing, so adding a shard only moves about `1 / N` of the
+//! sessions to it.
+
+use crate::storage::{IdPage, SessionStore};
+use crate::{Error, SessionInner};
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::sync::Arc;
+use time::Duration;
+
+/// Number of points each shard occupies on the hash ring
+const VIRTUAL_NODES: usize = 160;
+
+/// Hashes `value` onto the ring
+///
+/// Uses the first 8 bytes of its SHA-256 digest, which is stable across processes,
+/// platforms and compiler versions so every instance routes IDs identically.
+fn ring_hash(value: &str) -> u64 {
+    u64::from_str_radix(&sha256::digest(value)[..16], 16).unwrap_or_default()
+}
+
+/// Session storage wrapper routing each session to one of several shards
+///
+/// Each shard is placed on the ring under its name; routing only depends on the
+/// shard names, not on their order. Shards created with `new` are named after their
+/// position, so new shards must be appended at the end to keep existing sessions in
+/// place; use `named` to give shards stable names instead.
+///
+/// # Type Parameters
+/// * `S` - The session storage backend of every shard
+#[derive(Clone)]
+pub struct ShardedSessionStore<S>
+where
+    S: SessionStore,
+{
+    /// Shard backends
+    shards: Arc<Vec<S>>,
+    /// Ring points sorted by hash, each pointing to a shard index
+    ring: Arc<Vec<(u64, usize)>>,
+}
+
+impl<S> ShardedSessionStore<S>
+where
+    S: SessionStore,
+{
+    /// Creates a new ShardedSessionStore with shards named `shard-0`, `shard-1`, ...
+    ///
+    /// # Arguments
+    /// * `shards` - Backends to spread sessions over
+    ///
+    /// # Panics
+    /// Panics if no shard is given
+    pub fn new(shards: Vec<S>) -> Self {
+        ShardedSessionStore::named(
+            shards
+                .into_iter()
+                .enumerate()
+                .map(|(index, shard)| (format!("shard-{index}"), shard))
+                .collect(),
+        )
+    }
+    /// Creates a new ShardedSessionStore with explicitly named shards
+    ///
+    /// # Arguments
+    /// * `shards` - Backends to spread sessions over, each with a unique, stable name
+    ///
+    /// # Panics
+    /// Panics if no shard is given or if two shards share a name
+    pub fn named(shards: Vec<(String, S)>) -> Self {
+        assert!(!shards.is_empty(), "at least one shard is required");
+        let mut names = HashSet::with_capacity(shards.len());
+        assert!(
+            shards.iter().all(|(name, _)| names.insert(name)),
+            "shard names must be unique"
+        );
+        let mut ring = Vec::with_capacity(shards.len() * VIRTUAL_NODES);
+        for (index, (name, _)) in shards.iter().enumerate() {
+            for node in 0..VIRTUAL_NODES {
+                ring.push((ring_hash(&format!("{name}#{node}")), index));
+            }
+        }
+        ring.sort_unstable();
+        ShardedSessionStore {
+            shards: Arc::new(shards.into_iter().map(|(_, shard)| shard).collect()),
+            ring: Arc::new(ring),
+        }
+    }
+    /// Returns all shard backends
+    pub fn shards(&self) -> &[S] {
+        &self.shards
+    }
+    /// Returns the shard responsible for session `key`
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to route
+    pub fn shard(&self, key: &str) -> &S {
+        let hash = ring_hash(key);
+        let point = self.ring.partition_point(|(at, _)| *at < hash);
+        let (_, index) = self.ring[point % self.ring.len()];
+        &self.shards[index]
+    }
+}
+
+#[async_trait]
+impl<S> SessionStore for ShardedSessionStore<S>
+where
+    S: SessionStore,
+{
+    /// Retrieves a session from its shard
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to look up
+    ///
+    /// # Returns
+    /// Ok(Some(SessionInner)) if found, Ok(None) if missing, Err(Error) otherwise
+    async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error> {
+        self.shard(key).get(key).await
+    }
+
+    /// Stores a session in its shard
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if storage fails
+    async fn set(&self, key: &str, value: SessionInner) -> Result<(), Error> {
+        self.shard(key).set(key, value).await
+    }
+
+    /// Saves a session with its expiry in its shard
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    /// * `ttl` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if storage fails
+    async fn save(&self, key: &str, value: SessionInner, ttl: Duration) -> Result<(), Error> {
+        self.shard(key).save(key, value, ttl).await
+    }
+
+    /// Removes a session from its shard
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to remove
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if removal fails
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        self.shard(key).remove(key).await
+    }
+
+    /// Sets expiration time for a session in its shard
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to update
+    /// * `expire_time` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if expiration update fails
+    async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), Error> {
+        self.shard(key).expire(key, expire_time).await
+    }
+
+    /// Removes all sessions from every shard concurrently
+    ///
+    /// Every shard is cleared even if some of them fail.
+    ///
+    /// # Returns
+    /// Ok(count) with the total number of sessions removed, or the first shard error
+    async fn clear(&self) -> Result<usize, Error> {
+        futures::future::join_all(self.shards.iter().map(|shard| shard.clear()))
+            .await
+            .into_iter()
+            .sum()
+    }
+
+    /// Counts the sessions of every shard concurrently
+    ///
+    /// # Returns
+    /// Ok(count) with the total number of sessions, Err(Error) if any shard fails
+    async fn count(&self) -> Result<usize, Error> {
+        futures::future::join_all(self.shards.iter().map(|shard| shard.count()))
+            .await
+            .into_iter()
+            .sum()
+    }
+
+    /// Lists one page of session IDs, walking the shards one after another
+    ///
+    /// The cursor records the shard and the cursor of that shard.
+    ///
+    /// # Arguments
+    /// * `cursor` - Cursor returned by the previous call, None for the first page
+    /// * `limit` - Approximate number of IDs to return
+    ///
+    /// # Returns
+    /// Ok(IdPage) with the IDs and the next cursor, Err(Error) if a shard fails
+    async fn ids(&self, cursor: Option<&str>, limit: usize) -> Result<IdPage, Error> {
+        let (index, inner) = match cursor {
+            None => (0, None),
+            Some(value) => {
+                let (index, inner) = value
+                    .split_once(':')
+                    .ok_or_else(|| Error::InvalidCursor(value.to_string()))?;
+                let index = index
+                    .parse::<usize>()
+                    .map_err(|_| Error::InvalidCursor(value.to_string()))?;
+                (index, (!inner.is_empty()).then_some(inner))
+            }
+        };
+        let Some(shard) = self.shards.get(index) else {
+            return Err(Error::InvalidCursor(cursor.unwrap_or_default().to_string()));
+        };
+        let page = shard.ids(inner, limit).await?;
+        let cursor = match page.cursor {
+            Some(next) => Some(format!("{index}:{next}")),
+            None if index + 1 < self.shards.len() => Some(format!("{}:", index + 1)),
+            None => None,
+        };
+        Ok(IdPage {
+            ids: page.ids,
+            cursor,
+        })
+    }
+
+    /// Returns the remaining lifetime of a session from its shard
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to look up
+    ///
+    /// # Returns
+    /// Ok(Some(duration)) if the session expires, Ok(None) if it never expires,
+    /// Err(Error::NotFound) if it does not exist
+    async fn ttl(&self, key: &str) -> Result<Option<Duration>, Error> {
+        self.shard(key).ttl(key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Store without data, only identifying which shard a key was routed to
+    #[derive(Clone, Debug, PartialEq)]
+    struct Shard(usize);
+
+    #[async_trait]
+    impl SessionStore for Shard {
+        async fn get(&self, _: &str) -> Result<Option<SessionInner>, Error> {
+            Ok(None)
+        }
+        async fn set(&self, _: &str, _: SessionInner) -> Result<(), Error> {
+            Ok(())
+        }
+        async fn remove(&self, _: &str) -> Result<(), Error> {
+            Ok(())
+        }
+        async fn expire(&self, _: &str, _: Duration) -> Result<(), Error> {
+            Ok(())
+        }
+        async fn clear(&self) -> Result<usize, Error> {
+            Ok(0)
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "shard names must be unique")]
+    fn duplicate_shard_names_are_rejected() {
+        ShardedSessionStore::named(vec![
+            ("a".to_string(), Shard(0)),
+            ("b".to_string(), Shard(1)),
+            ("a".to_string(), Shard(2)),
+        ]);
+    }
+
+    fn keys() -> impl Iterator<Item = String> {
+        (0..2000).map(|n| format!("session-{n}"))
+    }
+
+    #[test]
+    fn appending_a_shard_only_moves_keys_to_the_new_shard() {
+        let before = ShardedSessionStore::new((0..3).map(Shard).collect());
+        let after = ShardedSessionStore::new((0..4).map(Shard).collect());
+        let mut moved = 0;
+        for key in keys() {
+            let (old, new) = (before.shard(&key), after.shard(&key));
+            if old != new {
+                assert_eq!(new, &Shard(3), "{key} moved between existing shards");
+                moved += 1;
+            }
+        }
+        assert!((300..700).contains(&moved), "{moved} of 2000 keys moved");
+    }
+
+    #[test]
+    fn routing_depends_on_names_not_order() {
+        let forward = ShardedSessionStore::named(vec![
+            ("a".to_string(), Shard(0)),
+            ("b".to_string(), Shard(1)),
+        ]);
+        let reversed = ShardedSessionStore::named(vec![
+            ("b".to_string(), Shard(1)),
+            ("a".to_string(), Shard(0)),
+        ]);
+        for key in keys() {
+            assert_eq!(forward.shard(&key), reversed.shard(&key));
+            assert_eq!(forward.shard(&key), forward.shard(&key));
+        }
+    }
+}
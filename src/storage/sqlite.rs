@@ -0,0 +1,404 @@
+//! SQLite session storage implementation
+//!
+//! This module provides a session store backed by a single SQLite table, suited for
+//! single-binary deployments where running Redis would be overkill. The table is
+//! created automatically and holds one row per session:
+//!
+//! | column       | type                | content                                  |
+//! |--------------|---------------------|------------------------------------------|
+//! | `id`         | TEXT PRIMARY KEY    | session ID                               |
+//! | `data`       | TEXT                | JSON object of the session data          |
+//! | `expires_at` | INTEGER NULL        | expiry as unix timestamp in seconds      |
+
+use crate::storage::{SessionStore, spawn_cleanup_task, valid_identifier};
+use crate::{Error, SessionInner};
+use async_trait::async_trait;
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::collections::HashMap;
+use time::{Duration, OffsetDateTime};
+use tokio::task::JoinHandle;
+
+/// SQLite-backed session storage implementation
+///
+/// Expiry is enforced on every read; expired rows are physically deleted by
+/// `cleanup`, which can be scheduled with `spawn_cleanup`.
+#[derive(Clone)]
+pub struct SqliteSessionStorage {
+    /// SQLite connection pool
+    pub pool: SqlitePool,
+    /// Name of the sessions table
+    pub table: String,
+}
+
+impl SqliteSessionStorage {
+    /// Creates a new SqliteSessionStorage using the `rsession` table
+    ///
+    /// The table is created if it does not exist yet.
+    ///
+    /// # Arguments
+    /// * `pool` - A sqlx SQLite connection pool
+    ///
+    /// # Returns
+    /// Ok(SqliteSessionStorage) if the schema is ready, Err(Error) otherwise
+    pub async fn new(pool: SqlitePool) -> Result<Self, Error> {
+        SqliteSessionStorage::with_table(pool, "rsession").await
+    }
+    /// Creates a new SqliteSessionStorage using a custom table name
+    ///
+    /// The table is created if it does not exist yet.
+    ///
+    /// # Arguments
+    /// * `pool` - A sqlx SQLite connection pool
+    /// * `table` - Table name made of ASCII letters, digits and underscores
+    ///
+    /// # Panics
+    /// Panics if the table name is not a plain SQL identifier
+    pub async fn with_table(pool: SqlitePool, table: &str) -> Result<Self, Error> {
+        assert!(
+            valid_identifier(table),
+            "table must be a plain SQL identifier"
+        );
+        let storage = SqliteSessionStorage {
+            pool,
+            table: table.to_string(),
+        };
+        storage.migrate().await?;
+        Ok(storage)
+    }
+
+    /// Creates the sessions table and its expiry index if they do not exist
+    pub async fn migrate(&self) -> Result<(), Error> {
+        sqlx::query(&format!(
+            "CREATE TABLE IF NOT EXISTS {0} (\
+                id TEXT PRIMARY KEY NOT NULL, \
+                data TEXT NOT NULL, \
+                expires_at INTEGER NULL\
+            )",
+            self.table
+        ))
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(&format!(
+            "CREATE INDEX IF NOT EXISTS {0}_expires_at ON {0} (expires_at)",
+            self.table
+        ))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Deletes every expired session row
+    ///
+    /// # Returns
+    /// Ok(count) with the number of rows deleted, Err(Error) if the query fails
+    pub async fn cleanup(&self) -> Result<u64, Error> {
+        sqlx::query(&format!(
+            "DELETE FROM {} WHERE expires_at IS NOT NULL AND expires_at <= ?",
+            self.table
+        ))
+        .bind(OffsetDateTime::now_utc().unix_timestamp())
+        .execute(&self.pool)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(Error::from)
+    }
+
+    /// Deletes expired rows every `interval` from a background task
+    ///
+    /// Abort the returned handle to stop it.
+    ///
+    /// # Panics
+    /// Panics if the interval is not positive or if called outside a tokio runtime
+    pub fn spawn_cleanup(&self, interval: Duration) -> JoinHandle<()> {
+        let storage = self.clone();
+        spawn_cleanup_task(interval, move || {
+            let storage = storage.clone();
+            async move { storage.cleanup().await }
+        })
+    }
+}
+
+#[async_trait]
+impl SessionStore for SqliteSessionStorage {
+    /// Retrieves a session from SQLite
+    ///
+    /// Rows whose expiry has passed are treated as missing.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to look up
+    ///
+    /// # Returns
+    /// Ok(Some(SessionInner)) if found and not expired, Ok(None) if missing or expired,
+    /// Err(Error) if the query fails
+    async fn get(&self, key: &str) -> Result<Option<SessionInner>, Error> {
+        let row = sqlx::query(&format!(
+            "SELECT data FROM {} WHERE id = ? AND (expires_at IS NULL OR expires_at > ?)",
+            self.table
+        ))
+        .bind(key)
+        .bind(OffsetDateTime::now_utc().unix_timestamp())
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let data = row.try_get::<String, _>("data")?;
+        let map = serde_json::from_str::<HashMap<String, String>>(&data)?;
+        Ok(Some(SessionInner::loaded(key.to_string(), map)))
+    }
+
+    /// Stores a session in SQLite
+    ///
+    /// Inserts or replaces the row; like a Redis `SET`, this clears any expiry.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if serialization or storage fails
+    async fn set(&self, key: &str, value: SessionInner) -> Result<(), Error> {
+        sqlx::query(&format!(
+            "INSERT INTO {} (id, data, expires_at) VALUES (?, ?, NULL) \
+             ON CONFLICT (id) DO UPDATE SET data = excluded.data, expires_at = NULL",
+            self.table
+        ))
+        .bind(key)
+        .bind(serde_json::to_string(&value.data)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Stores a session in SQLite together with its expiry in a single statement
+    ///
+    /// A non-positive duration removes the session.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to associate with the session data
+    /// * `value` - SessionInner instance containing the data to store
+    /// * `ttl` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if serialization or storage fails
+    async fn save(&self, key: &str, value: SessionInner, ttl: Duration) -> Result<(), Error> {
+        if !ttl.is_positive() {
+            return self.remove(key).await;
+        }
+        sqlx::query(&format!(
+            "INSERT INTO {} (id, data, expires_at) VALUES (?, ?, ?) \
+             ON CONFLICT (id) DO UPDATE SET data = excluded.data, expires_at = excluded.expires_at",
+            self.table
+        ))
+        .bind(key)
+        .bind(serde_json::to_string(&value.data)?)
+        .bind((OffsetDateTime::now_utc() + ttl).unix_timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Removes a session from SQLite
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to remove
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if removal fails
+    async fn remove(&self, key: &str) -> Result<(), Error> {
+        sqlx::query(&format!("DELETE FROM {} WHERE id = ?", self.table))
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Sets expiration time for a session in SQLite
+    ///
+    /// A non-positive duration deletes the session immediately.
+    ///
+    /// # Arguments
+    /// * `key` - Session identifier to update
+    /// * `expire_time` - Duration until the session expires
+    ///
+    /// # Returns
+    /// Ok(()) if successful, Err(Error) if expiration update fails
+    async fn expire(&self, key: &str, expire_time: Duration) -> Result<(), Error> {
+        if !expire_time.is_positive() {
+            return self.remove(key).await;
+        }
+        sqlx::query(&format!(
+            "UPDATE {} SET expires_at = ? WHERE id = ?",
+            self.table
+        ))
+        .bind((OffsetDateTime::now_utc() + expire_time).unix_timestamp())
+        .bind(key)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Removes all sessions from this storage's table
+    ///
+    /// # Returns
+    /// Ok(count) with the number of sessions removed, Err(Error) if clear operation fails
+    async fn clear(&self) -> Result<usize, Error> {
+        sqlx::query(&format!("DELETE FROM {}", self.table))
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() as usize)
+            .map_err(Error::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::{session, user};
+    use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+
+    /// Opens a private in-memory database
+    ///
+    /// Every connection to `sqlite::memory:` opens a distinct database, so the pool
+    /// keeps exactly one connection alive.
+    async fn pool() -> SqlitePool {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    async fn expires_at(storage: &SqliteSessionStorage, id: &str) -> Option<i64> {
+        sqlx::query_scalar(&format!(
+            "SELECT expires_at FROM {} WHERE id = ?",
+            storage.table
+        ))
+        .bind(id)
+        .fetch_one(&storage.pool)
+        .await
+        .unwrap()
+    }
+
+    /// Moves the expiry of a session into the past without waiting for it
+    async fn backdate(storage: &SqliteSessionStorage, id: &str) {
+        sqlx::query(&format!(
+            "UPDATE {} SET expires_at = ? WHERE id = ?",
+            storage.table
+        ))
+        .bind(OffsetDateTime::now_utc().unix_timestamp() - 1)
+        .bind(id)
+        .execute(&storage.pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn migrate_creates_the_table_and_is_idempotent() {
+        let pool = pool().await;
+        let storage = SqliteSessionStorage::with_table(pool.clone(), "sessions")
+            .await
+            .unwrap();
+        storage.migrate().await.unwrap();
+        let indexes: Vec<String> = sqlx::query_scalar(
+            "SELECT name FROM sqlite_master WHERE tbl_name = 'sessions' AND type = 'index' \
+             AND name NOT LIKE 'sqlite_%'",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(indexes, ["sessions_expires_at"]);
+    }
+
+    #[tokio::test]
+    async fn wal_databases_share_sessions_across_connections() {
+        let path = std::env::temp_dir().join(format!("rsession-sqlite-{}.db", std::process::id()));
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let writer = SqlitePoolOptions::new()
+            .connect_with(options.clone())
+            .await
+            .unwrap();
+        let reader = SqlitePoolOptions::new()
+            .connect_with(options)
+            .await
+            .unwrap();
+        let first = SqliteSessionStorage::new(writer).await.unwrap();
+        // Migrating an existing table from another pool is a no-op
+        let second = SqliteSessionStorage::new(reader).await.unwrap();
+        let mode: String = sqlx::query_scalar("PRAGMA journal_mode")
+            .fetch_one(&second.pool)
+            .await
+            .unwrap();
+        assert_eq!(mode, "wal");
+        first
+            .save("a", session("a", "alice"), Duration::minutes(5))
+            .await
+            .unwrap();
+        assert_eq!(user(&second, "a").await.as_deref(), Some("alice"));
+        second.remove("a").await.unwrap();
+        assert!(first.get("a").await.unwrap().is_none());
+        first.pool.close().await;
+        second.pool.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            std::fs::remove_file(format!("{}{suffix}", path.display())).ok();
+        }
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "plain SQL identifier")]
+    async fn unsafe_table_names_are_rejected() {
+        SqliteSessionStorage::with_table(pool().await, "rsession; DROP TABLE x")
+            .await
+            .ok();
+    }
+
+    #[tokio::test]
+    async fn set_clears_the_expiry_column() {
+        let storage = SqliteSessionStorage::new(pool().await).await.unwrap();
+        storage
+            .save("a", session("a", "alice"), Duration::minutes(5))
+            .await
+            .unwrap();
+        assert!(expires_at(&storage, "a").await.is_some());
+        storage.set("a", session("a", "bob")).await.unwrap();
+        assert_eq!(expires_at(&storage, "a").await, None);
+    }
+
+    #[tokio::test]
+    async fn expired_rows_are_hidden_then_cleaned_up() {
+        let storage = SqliteSessionStorage::new(pool().await).await.unwrap();
+        storage
+            .save("short", session("short", "alice"), Duration::minutes(5))
+            .await
+            .unwrap();
+        storage.set("long", session("long", "bob")).await.unwrap();
+        backdate(&storage, "short").await;
+        assert!(storage.get("short").await.unwrap().is_none());
+        assert_eq!(storage.cleanup().await.unwrap(), 1);
+        assert_eq!(storage.cleanup().await.unwrap(), 0);
+        assert_eq!(user(&storage, "long").await.as_deref(), Some("bob"));
+    }
+
+    #[tokio::test]
+    async fn clear_only_touches_its_own_table() {
+        let pool = pool().await;
+        let first = SqliteSessionStorage::with_table(pool.clone(), "first")
+            .await
+            .unwrap();
+        let second = SqliteSessionStorage::with_table(pool, "second")
+            .await
+            .unwrap();
+        first.set("a", session("a", "alice")).await.unwrap();
+        first.set("b", session("b", "bob")).await.unwrap();
+        second.set("a", session("a", "carol")).await.unwrap();
+        assert_eq!(first.clear().await.unwrap(), 2);
+        assert!(first.get("a").await.unwrap().is_none());
+        assert_eq!(user(&second, "a").await.as_deref(), Some("carol"));
+    }
+}
@@ -0,0 +1,20 @@
+//! Fixtures shared by the unit tests of the storage backends
+
+use crate::SessionInner;
+use crate::storage::SessionStore;
+
+/// Returns a session holding `user` under the `user` key
+pub(crate) fn session(id: &str, user: &str) -> SessionInner {
+    let mut inner = SessionInner::new(id.to_string());
+    inner.set("user", user).unwrap();
+    inner
+}
+
+/// Loads session `id` and returns its `user` value
+pub(crate) async fn user(storage: &impl SessionStore, id: &str) -> Option<String> {
+    storage
+        .get(id)
+        .await
+        .unwrap()
+        .and_then(|inner| inner.get::<String>("user"))
+}
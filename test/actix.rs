@@ -10,8 +10,8 @@ async fn main() {
         let redis = deadpool_redis::Config::from_url("redis://192.168.22.129:6379")
             .create_pool(Some(deadpool_redis::Runtime::Tokio1))
             .unwrap();
-        let session = rsession::SessionBuilder::default();
-        let mut store = RedisSessionStorage::new(redis, RandKey::RandomSha256(128));
+        let session = rsession::SessionBuilder::default().rand_key(RandKey::RandomSha256(128));
+        let mut store = RedisSessionStorage::new(redis);
         store.set_prefix("actix_test_session:");
         App::new()
             .wrap(ActixSessionMiddleware::new(session.clone(), store.clone()))
@@ -25,12 +25,12 @@ async fn main() {
 }
 
 async fn index(session: Session) -> String {
-    return if session.get::<i32>("count").is_err() {
+    if session.get::<i32>("count").is_err() {
         session.set("count", 1).ok();
         format!("count: {:?}", session.get::<i32>("count").unwrap())
     } else {
         let count = session.get::<i32>("count").unwrap();
         session.set("count", count + 1).ok();
         format!("count: {:?}", session.get::<i32>("count").unwrap())
-    };
+    }
 }
@@ -1,8 +1,8 @@
 use axum::response::IntoResponse;
 use axum::routing::get;
+use rsession::Session;
 use rsession::framework::axum::AxumSessionMiddlewareLayer;
 use rsession::redis::RedisSessionStorage;
-use rsession::{RandKey, Session};
 use tokio::net::TcpListener;
 
 #[tokio::main]
@@ -13,7 +13,7 @@ async fn main() {
         .unwrap();
 
     let session = rsession::SessionBuilder::default();
-    let mut store = RedisSessionStorage::new(redis, RandKey::UuidV7);
+    let mut store = RedisSessionStorage::new(redis);
     store.set_prefix("actix_session:");
     let app =
         axum::Router::new()
@@ -32,12 +32,12 @@ async fn main() {
 
 #[axum::debug_handler]
 pub async fn index(session: Session) -> impl IntoResponse {
-    return if session.get::<i32>("count").is_err() {
+    if session.get::<i32>("count").is_err() {
         session.set("count", 1).ok();
         format!("count: {:?}", session.get::<i32>("count").unwrap())
     } else {
         let count = session.get::<i32>("count").unwrap();
         session.set("count", count + 1).ok();
         format!("count: {:?}", session.get::<i32>("count").unwrap())
-    };
+    }
 }
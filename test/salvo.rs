@@ -1,4 +1,3 @@
-use rsession::RandKey;
 use rsession::framework::salvo::{SalvoSessionMiddleware, SessionDepotExt};
 use rsession::redis::RedisSessionStorage;
 use salvo::prelude::*;
@@ -11,7 +10,7 @@ async fn main() {
         .unwrap();
 
     let session = rsession::SessionBuilder::default();
-    let mut store = RedisSessionStorage::new(redis, RandKey::UuidV7);
+    let mut store = RedisSessionStorage::new(redis);
     store.set_prefix("actix_session:");
     let acceptor = TcpListener::new("0.0.0.0:8080").bind().await;
     let router = Router::new()
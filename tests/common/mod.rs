@@ -0,0 +1,71 @@
+//! Fixtures shared by the integration tests of the storage backends
+
+// Every test binary compiles this module but uses only part of it
+#![allow(dead_code)]
+
+use rsession::{SessionInner, SessionStore};
+use time::Duration;
+
+/// Returns a session holding `user` under the `user` key
+pub fn session(id: &str, user: &str) -> SessionInner {
+    let mut inner = SessionInner::new(id.to_string());
+    inner.set("user", user).unwrap();
+    inner
+}
+
+/// Loads session `id` and returns its `user` value
+pub async fn user(storage: &impl SessionStore, id: &str) -> Option<String> {
+    storage
+        .get(id)
+        .await
+        .unwrap()
+        .and_then(|inner| inner.get::<String>("user"))
+}
+
+/// Checks the `SessionStore` behaviour every backend shares
+///
+/// Covers `save`/`get` round trips, upserts through `save` and `set`, `expire` on
+/// present and missing sessions and idempotent removal.
+pub async fn check_round_trip(storage: &impl SessionStore) {
+    storage
+        .save("a", session("a", "alice"), Duration::minutes(5))
+        .await
+        .unwrap();
+    assert_eq!(user(storage, "a").await.as_deref(), Some("alice"));
+    assert!(storage.get("b").await.unwrap().is_none());
+    storage
+        .save("a", session("a", "bob"), Duration::minutes(5))
+        .await
+        .unwrap();
+    assert_eq!(user(storage, "a").await.as_deref(), Some("bob"));
+    storage.set("a", session("a", "carol")).await.unwrap();
+    storage.expire("a", Duration::minutes(10)).await.unwrap();
+    assert_eq!(user(storage, "a").await.as_deref(), Some("carol"));
+    // Expiring an unknown session does not create it
+    storage.expire("b", Duration::minutes(5)).await.unwrap();
+    assert!(storage.get("b").await.unwrap().is_none());
+    storage.expire("a", Duration::ZERO).await.unwrap();
+    assert!(storage.get("a").await.unwrap().is_none());
+    storage
+        .save("c", session("c", "dave"), Duration::minutes(5))
+        .await
+        .unwrap();
+    storage.remove("c").await.unwrap();
+    storage.remove("c").await.unwrap();
+    assert!(storage.get("c").await.unwrap().is_none());
+}
+
+/// Checks that clearing `first` leaves the sessions of `second` untouched
+///
+/// # Returns
+/// The count reported by `first.clear()` for two stored sessions
+pub async fn check_clear_isolation(first: &impl SessionStore, second: &impl SessionStore) -> usize {
+    first.set("a", session("a", "alice")).await.unwrap();
+    first.set("b", session("b", "bob")).await.unwrap();
+    second.set("a", session("a", "carol")).await.unwrap();
+    let cleared = first.clear().await.unwrap();
+    assert!(first.get("a").await.unwrap().is_none());
+    assert!(first.get("b").await.unwrap().is_none());
+    assert_eq!(user(second, "a").await.as_deref(), Some("carol"));
+    cleared
+}
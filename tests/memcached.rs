@@ -0,0 +1,106 @@
+//! Integration tests against a running memcached server (1.6 or later)
+//!
+//! Ignored by default. Run with a server the tests may write to:
+//!
+//! ```sh
+//! RSESSION_MEMCACHED_URL=tcp://localhost:11211 \
+//!     cargo test --features memcached --test memcached -- --ignored
+//! ```
+#![cfg(feature = "memcached")]
+
+mod common;
+
+use async_memcached::AsciiProtocol;
+use common::{check_clear_isolation, check_round_trip, session, user};
+use rsession::memcached::MemcachedSessionStorage;
+use rsession::{Error, SessionStore};
+use time::Duration;
+
+/// Connects to the server with a key prefix private to this test
+fn storage(name: &str) -> MemcachedSessionStorage {
+    let url = std::env::var("RSESSION_MEMCACHED_URL").expect("RSESSION_MEMCACHED_URL is not set");
+    let manager = deadpool_memcached::Manager::new(url);
+    let pool = deadpool_memcached::Pool::builder(manager).build().unwrap();
+    MemcachedSessionStorage::new(pool).set_prefix(&format!(
+        "rsession_test:{}:{}:",
+        name,
+        std::process::id()
+    ))
+}
+
+/// Reads the namespace version of a store straight from the server
+async fn version(storage: &MemcachedSessionStorage) -> u64 {
+    let mut conn = storage.pool.get().await.unwrap();
+    let value = conn
+        .get(format!("{}ns", storage.prefix))
+        .await
+        .unwrap()
+        .and_then(|value| value.data)
+        .expect("namespace version is not set");
+    String::from_utf8(value).unwrap().trim().parse().unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_MEMCACHED_URL"]
+async fn behaves_like_a_session_store() {
+    check_round_trip(&storage("round_trip")).await;
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_MEMCACHED_URL"]
+async fn sessions_expire() {
+    let storage = storage("expiry");
+    storage
+        .save("short", session("short", "alice"), Duration::seconds(1))
+        .await
+        .unwrap();
+    storage.set("long", session("long", "bob")).await.unwrap();
+    storage.expire("long", Duration::minutes(5)).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(2100)).await;
+    assert!(storage.get("short").await.unwrap().is_none());
+    assert_eq!(user(&storage, "long").await.as_deref(), Some("bob"));
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_MEMCACHED_URL"]
+async fn clear_only_touches_its_own_prefix() {
+    let first = storage("clear_first");
+    let second = storage("clear_second");
+    assert_eq!(check_clear_isolation(&first, &second).await, 0);
+    first.set("a", session("a", "dave")).await.unwrap();
+    assert_eq!(user(&first, "a").await.as_deref(), Some("dave"));
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_MEMCACHED_URL"]
+async fn clear_bumps_the_namespace_version() {
+    let storage = storage("namespace");
+    storage.set("a", session("a", "alice")).await.unwrap();
+    let before = version(&storage).await;
+    storage.clear().await.unwrap();
+    assert_eq!(version(&storage).await, before + 1);
+    assert!(storage.get("a").await.unwrap().is_none());
+    // The old item is left for memcached to evict rather than deleted
+    let mut conn = storage.pool.get().await.unwrap();
+    let old = conn
+        .get(format!("{}{}:a", storage.prefix, before))
+        .await
+        .unwrap();
+    assert!(old.is_some());
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_MEMCACHED_URL"]
+async fn ids_longer_than_a_memcached_key_are_rejected() {
+    let storage = storage("long_id");
+    let id = "a".repeat(250);
+    assert!(storage.get(&id).await.unwrap().is_none());
+    let saved = storage
+        .save(&id, session(&id, "alice"), Duration::minutes(5))
+        .await;
+    assert!(matches!(saved, Err(Error::InvalidId(_))));
+    assert!(matches!(
+        storage.set(&id, session(&id, "alice")).await,
+        Err(Error::InvalidId(_))
+    ));
+}
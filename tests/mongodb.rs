@@ -0,0 +1,136 @@
+//! Integration tests against a running MongoDB server
+//!
+//! Ignored by default. Run with a server the tests may create collections on:
+//!
+//! ```sh
+//! RSESSION_MONGODB_URL=mongodb://localhost:27017 \
+//!     cargo test --features mongodb --test mongodb -- --ignored
+//! ```
+#![cfg(feature = "mongodb")]
+
+mod common;
+
+use common::{check_clear_isolation, check_round_trip, session, user};
+use mongodb::Client;
+use mongodb::bson::{Document, doc};
+use rsession::SessionStore;
+use rsession::mongodb::MongoSessionStorage;
+use time::Duration;
+
+/// Connects to the server and creates a collection private to this test
+async fn storage(name: &str) -> MongoSessionStorage {
+    let url = std::env::var("RSESSION_MONGODB_URL").expect("RSESSION_MONGODB_URL is not set");
+    let client = Client::with_uri_str(&url).await.unwrap();
+    let collection = client
+        .database("rsession_test")
+        .collection::<Document>(&format!("{}_{}", name, std::process::id()));
+    collection.drop().await.unwrap();
+    MongoSessionStorage::with_collection(collection)
+        .await
+        .unwrap()
+}
+
+async fn drop_collection(storage: MongoSessionStorage) {
+    storage.collection.drop().await.unwrap();
+}
+
+/// Counts the documents of the collection, expired or not
+async fn documents(storage: &MongoSessionStorage) -> u64 {
+    storage.collection.count_documents(doc! {}).await.unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_MONGODB_URL"]
+async fn migrate_creates_the_ttl_index_and_is_idempotent() {
+    let storage = storage("migrate").await;
+    storage.migrate().await.unwrap();
+    let mut ttl_indexes = Vec::new();
+    let mut cursor = storage.collection.list_indexes().await.unwrap();
+    while cursor.advance().await.unwrap() {
+        let index = cursor.deserialize_current().unwrap();
+        if index.keys == doc! { "expiresAt": 1 } {
+            ttl_indexes.push(index.options.and_then(|options| options.expire_after));
+        }
+    }
+    assert_eq!(ttl_indexes, [Some(std::time::Duration::ZERO)]);
+    drop_collection(storage).await;
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_MONGODB_URL"]
+async fn behaves_like_a_session_store() {
+    let storage = storage("round_trip").await;
+    check_round_trip(&storage).await;
+    drop_collection(storage).await;
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_MONGODB_URL"]
+async fn set_and_save_store_the_documented_layout() {
+    let storage = storage("layout").await;
+    storage.set("a", session("a", "alice")).await.unwrap();
+    storage
+        .save("b", session("b", "bob"), Duration::minutes(5))
+        .await
+        .unwrap();
+    let find = |id: &'static str| storage.collection.find_one(doc! { "_id": id });
+    let a = find("a").await.unwrap().unwrap();
+    assert!(a.get("expiresAt").unwrap().as_null().is_some());
+    assert_eq!(
+        a.get_document("data").unwrap(),
+        &doc! { "user": "\"alice\"" }
+    );
+    let b = find("b").await.unwrap().unwrap();
+    assert!(b.get_datetime("expiresAt").is_ok());
+    drop_collection(storage).await;
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_MONGODB_URL"]
+async fn non_positive_expiry_removes_the_session() {
+    let storage = storage("expire").await;
+    for (id, ttl) in [("a", Duration::ZERO), ("b", Duration::seconds(-5))] {
+        storage
+            .save(id, session(id, "alice"), Duration::minutes(5))
+            .await
+            .unwrap();
+        storage.expire(id, ttl).await.unwrap();
+        assert!(storage.get(id).await.unwrap().is_none());
+    }
+    storage
+        .save("c", session("c", "carol"), Duration::seconds(-1))
+        .await
+        .unwrap();
+    assert_eq!(documents(&storage).await, 0);
+    drop_collection(storage).await;
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_MONGODB_URL"]
+async fn expired_documents_are_hidden_before_the_ttl_monitor_runs() {
+    let storage = storage("expiry").await;
+    storage
+        .save(
+            "short",
+            session("short", "alice"),
+            Duration::milliseconds(200),
+        )
+        .await
+        .unwrap();
+    storage.set("long", session("long", "bob")).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(400)).await;
+    assert!(storage.get("short").await.unwrap().is_none());
+    assert_eq!(user(&storage, "long").await.as_deref(), Some("bob"));
+    drop_collection(storage).await;
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_MONGODB_URL"]
+async fn clear_only_touches_its_own_collection() {
+    let first = storage("clear_first").await;
+    let second = storage("clear_second").await;
+    assert_eq!(check_clear_isolation(&first, &second).await, 2);
+    assert_eq!(documents(&second).await, 1);
+    drop_collection(first).await;
+    drop_collection(second).await;
+}
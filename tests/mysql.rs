@@ -0,0 +1,113 @@
+//! Integration tests against a running MySQL or MariaDB server
+//!
+//! Ignored by default. Run with a database the tests may create tables in:
+//!
+//! ```sh
+//! RSESSION_MYSQL_URL=mysql://root@localhost/rsession \
+//!     cargo test --features mysql --test mysql -- --ignored
+//! ```
+#![cfg(feature = "mysql")]
+
+mod common;
+
+use common::{check_clear_isolation, check_round_trip, session, user};
+use rsession::SessionStore;
+use rsession::mysql::MySqlSessionStorage;
+use sqlx::mysql::MySqlPool;
+use time::Duration;
+
+/// Connects to the server and creates a table private to this test
+async fn storage(name: &str) -> MySqlSessionStorage {
+    let url = std::env::var("RSESSION_MYSQL_URL").expect("RSESSION_MYSQL_URL is not set");
+    let pool = MySqlPool::connect(&url).await.unwrap();
+    let table = format!("rsession_test_{}_{}", name, std::process::id());
+    sqlx::query(&format!("DROP TABLE IF EXISTS {table}"))
+        .execute(&pool)
+        .await
+        .unwrap();
+    MySqlSessionStorage::with_table(pool, &table).await.unwrap()
+}
+
+async fn drop_table(storage: MySqlSessionStorage) {
+    sqlx::query(&format!("DROP TABLE {}", storage.table))
+        .execute(&storage.pool)
+        .await
+        .unwrap();
+}
+
+/// Returns the `id` column of every row, expired or not
+async fn row_ids(storage: &MySqlSessionStorage) -> Vec<String> {
+    sqlx::query_scalar(&format!("SELECT id FROM {} ORDER BY id", storage.table))
+        .fetch_all(&storage.pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_MYSQL_URL"]
+async fn behaves_like_a_session_store() {
+    let storage = storage("round_trip").await;
+    check_round_trip(&storage).await;
+    drop_table(storage).await;
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_MYSQL_URL"]
+async fn migrate_is_idempotent() {
+    let storage = storage("migrate").await;
+    storage.set("a", session("a", "alice")).await.unwrap();
+    storage.migrate().await.unwrap();
+    assert_eq!(user(&storage, "a").await.as_deref(), Some("alice"));
+    drop_table(storage).await;
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_MYSQL_URL"]
+async fn long_and_non_ascii_ids_are_stored_hashed() {
+    let storage = storage("long_id").await;
+    let long = "a".repeat(1366);
+    for id in [long.as_str(), "sessión"] {
+        storage
+            .save(id, session(id, "alice"), Duration::minutes(5))
+            .await
+            .unwrap();
+        let loaded = storage.get(id).await.unwrap().unwrap();
+        assert_eq!(loaded.id(), id);
+        assert_eq!(loaded.get::<String>("user").as_deref(), Some("alice"));
+    }
+    let ids = row_ids(&storage).await;
+    assert_eq!(ids.len(), 2);
+    assert!(ids.iter().all(|id| id.starts_with('~') && id.len() == 65));
+    storage.remove(&long).await.unwrap();
+    assert_eq!(row_ids(&storage).await.len(), 1);
+    drop_table(storage).await;
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_MYSQL_URL"]
+async fn expired_rows_are_hidden_then_cleaned_up() {
+    let storage = storage("expiry").await;
+    storage
+        .save("short", session("short", "alice"), Duration::seconds(1))
+        .await
+        .unwrap();
+    storage
+        .save("long", session("long", "bob"), Duration::minutes(5))
+        .await
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(2100)).await;
+    assert!(storage.get("short").await.unwrap().is_none());
+    assert_eq!(storage.cleanup().await.unwrap(), 1);
+    assert_eq!(row_ids(&storage).await, ["long"]);
+    drop_table(storage).await;
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_MYSQL_URL"]
+async fn clear_only_touches_its_own_table() {
+    let first = storage("clear_first").await;
+    let second = storage("clear_second").await;
+    assert_eq!(check_clear_isolation(&first, &second).await, 2);
+    drop_table(first).await;
+    drop_table(second).await;
+}
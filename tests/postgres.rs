@@ -0,0 +1,109 @@
+//! Integration tests against a running PostgreSQL server
+//!
+//! Ignored by default. Run with a database the tests may create tables in:
+//!
+//! ```sh
+//! RSESSION_POSTGRES_URL=postgres://postgres@localhost/postgres \
+//!     cargo test --features postgres --test postgres -- --ignored
+//! ```
+#![cfg(feature = "postgres")]
+
+mod common;
+
+use common::{check_clear_isolation, check_round_trip, session, user};
+use rsession::SessionStore;
+use rsession::postgres::PostgresSessionStorage;
+use sqlx::postgres::PgPool;
+use time::Duration;
+
+/// Connects to the server and creates a table private to this test
+async fn storage(name: &str) -> PostgresSessionStorage {
+    let url = std::env::var("RSESSION_POSTGRES_URL").expect("RSESSION_POSTGRES_URL is not set");
+    let pool = PgPool::connect(&url).await.unwrap();
+    let table = format!("rsession_test_{}_{}", name, std::process::id());
+    sqlx::query(&format!("DROP TABLE IF EXISTS public.{table}"))
+        .execute(&pool)
+        .await
+        .unwrap();
+    PostgresSessionStorage::with_table(pool, "public", &table)
+        .await
+        .unwrap()
+}
+
+async fn drop_table(storage: PostgresSessionStorage) {
+    sqlx::query(&format!("DROP TABLE {}.{}", storage.schema, storage.table))
+        .execute(&storage.pool)
+        .await
+        .unwrap();
+}
+
+/// Counts the rows of the table, expired or not
+async fn rows(storage: &PostgresSessionStorage) -> i64 {
+    sqlx::query_scalar(&format!(
+        "SELECT count(*) FROM {}.{}",
+        storage.schema, storage.table
+    ))
+    .fetch_one(&storage.pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_POSTGRES_URL"]
+async fn behaves_like_a_session_store() {
+    let storage = storage("round_trip").await;
+    check_round_trip(&storage).await;
+    drop_table(storage).await;
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_POSTGRES_URL"]
+async fn set_clears_the_expiry_column() {
+    let storage = storage("upsert").await;
+    storage
+        .save("a", session("a", "alice"), Duration::minutes(5))
+        .await
+        .unwrap();
+    storage.set("a", session("a", "bob")).await.unwrap();
+    assert_eq!(user(&storage, "a").await.as_deref(), Some("bob"));
+    let expires_at: Option<i64> = sqlx::query_scalar(&format!(
+        "SELECT extract(epoch FROM expires_at)::BIGINT FROM {}.{} WHERE id = 'a'",
+        storage.schema, storage.table
+    ))
+    .fetch_one(&storage.pool)
+    .await
+    .unwrap();
+    assert_eq!(expires_at, None);
+    assert_eq!(rows(&storage).await, 1);
+    drop_table(storage).await;
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_POSTGRES_URL"]
+async fn expired_rows_are_hidden_then_cleaned_up() {
+    let storage = storage("expiry").await;
+    storage
+        .save("short", session("short", "alice"), Duration::seconds(1))
+        .await
+        .unwrap();
+    storage
+        .save("long", session("long", "bob"), Duration::minutes(5))
+        .await
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+    assert!(storage.get("short").await.unwrap().is_none());
+    assert_eq!(storage.cleanup().await.unwrap(), 1);
+    assert_eq!(rows(&storage).await, 1);
+    assert_eq!(user(&storage, "long").await.as_deref(), Some("bob"));
+    drop_table(storage).await;
+}
+
+#[tokio::test]
+#[ignore = "requires RSESSION_POSTGRES_URL"]
+async fn clear_only_touches_its_own_table() {
+    let first = storage("clear_first").await;
+    let second = storage("clear_second").await;
+    assert_eq!(check_clear_isolation(&first, &second).await, 2);
+    drop_table(first).await;
+    drop_table(second).await;
+}